@@ -1,19 +1,101 @@
-use std::{collections::HashMap, env, fs::File, io::BufReader};
+use std::{collections::HashMap, env, fs, fs::File, io, io::BufReader, path::PathBuf};
 
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct Config {
-    #[serde(rename = "languageServers")]
-    pub language_servers: HashMap<String, String>,
+    #[serde(
+        rename = "languageServers",
+        deserialize_with = "server_configs",
+        default
+    )]
+    pub language_servers: HashMap<String, Vec<ServerConfig>>, // Asked in this order
+    #[serde(default)]
+    pub languages: HashMap<String, LanguageConfig>,
+    // Colors of semantic token types, such as "keyword": "magenta"
+    #[serde(default)]
+    pub theme: HashMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LanguageConfig {
+    #[serde(rename = "formatOnSave", default)]
+    pub format_on_save: bool,
+    // Files of the language, added to the built-in ones
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    #[serde(rename = "fileNames", default)]
+    pub file_names: Vec<String>,
+    #[serde(default)]
+    pub interpreters: Vec<String>,
+    // Files marking the workspace folder, such as "Cargo.toml"
+    #[serde(rename = "rootMarkers", default)]
+    pub root_markers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub cwd: Option<String>,
+    #[serde(rename = "initializationOptions")]
+    pub initialization_options: Option<serde_json::Value>,
+    // Sections answered to workspace/configuration, such as "rust-analyzer"
+    #[serde(default)]
+    pub settings: serde_json::Value,
+    // "messages" or "verbose" to record the traffic from the start
+    pub trace: Option<String>,
+}
+
+// A server is either the command path alone or the whole object,
+// and a language has either a server or a list of them
+fn server_configs<'de, D>(deserializer: D) -> Result<HashMap<String, Vec<ServerConfig>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Command(String),
+        Server(ServerConfig),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entries {
+        One(Entry),
+        Many(Vec<Entry>),
+    }
+
+    let server = |entry| match entry {
+        Entry::Command(command) => ServerConfig {
+            command,
+            ..Default::default()
+        },
+        Entry::Server(server) => server,
+    };
+    let entries = HashMap::<String, Entries>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|(language, entries)| match entries {
+            Entries::One(entry) => (language, vec![server(entry)]),
+            Entries::Many(entries) => (language, entries.into_iter().map(server).collect()),
+        })
+        .collect())
 }
 
 pub fn load() -> Result<Config> {
     let mut config = read()?;
 
-    for (_, path) in config.language_servers.iter_mut() {
-        *path = shellexpand::full(&path)?.to_string();
+    for server in config.language_servers.values_mut().flatten() {
+        server.command = shellexpand::full(&server.command)?.to_string();
+        if let Some(cwd) = &mut server.cwd {
+            *cwd = shellexpand::full(cwd)?.to_string();
+        }
     }
 
     anyhow::Ok(config)
@@ -26,7 +108,93 @@ fn read() -> Result<Config> {
         env::var("HOME")? + "/.config"
     };
 
-    anyhow::Ok(serde_json::from_reader(BufReader::new(File::open(
-        xdg_config_home + "/sura/config.json",
-    )?))?)
+    // Without the file, files are edited without language servers
+    let file = match File::open(xdg_config_home + "/sura/config.json") {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return anyhow::Ok(Config::default()),
+        Err(err) => return Err(err.into()),
+    };
+    anyhow::Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+// Logs and other files the editor writes for itself
+pub fn state_dir() -> Result<PathBuf> {
+    let xdg_state_home = if let Ok(home) = env::var("XDG_STATE_HOME") {
+        home
+    } else {
+        env::var("HOME")? + "/.local/state"
+    };
+
+    let dir = PathBuf::from(xdg_state_home + "/sura");
+    fs::create_dir_all(&dir)?;
+    anyhow::Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers(json: &str) -> HashMap<String, Vec<ServerConfig>> {
+        serde_json::from_str::<Config>(json)
+            .unwrap()
+            .language_servers
+    }
+
+    #[test]
+    fn reads_a_command_alone() {
+        let servers = servers(r#"{"languageServers": {"rust": "rust-analyzer"}}"#);
+        assert_eq!(servers["rust"].len(), 1);
+        assert_eq!(servers["rust"][0].command, "rust-analyzer");
+        assert!(servers["rust"][0].args.is_empty());
+    }
+
+    #[test]
+    fn reads_an_object() {
+        let servers = servers(
+            r#"{"languageServers": {"python": {
+                "command": "pylsp", "args": ["-v"], "env": {"A": "1"}, "cwd": "/tmp",
+                "initializationOptions": {"b": 2}, "settings": {"pylsp": {}},
+                "trace": "verbose"
+            }}}"#,
+        );
+        assert_eq!(servers["python"].len(), 1);
+        let server = &servers["python"][0];
+        assert_eq!(server.command, "pylsp");
+        assert_eq!(server.args, ["-v"]);
+        assert_eq!(server.env["A"], "1");
+        assert_eq!(server.cwd.as_deref(), Some("/tmp"));
+        assert_eq!(
+            server.initialization_options,
+            Some(serde_json::json!({"b": 2}))
+        );
+        assert_eq!(server.settings, serde_json::json!({"pylsp": {}}));
+        assert_eq!(server.trace.as_deref(), Some("verbose"));
+    }
+
+    #[test]
+    fn reads_a_list_of_both_in_order() {
+        let servers = servers(
+            r#"{"languageServers": {"typescript": [
+                "typescript-language-server",
+                {"command": "eslint-lsp", "args": ["--stdio"]}
+            ]}}"#,
+        );
+        let commands: Vec<&str> = servers["typescript"]
+            .iter()
+            .map(|server| server.command.as_str())
+            .collect();
+        assert_eq!(commands, ["typescript-language-server", "eslint-lsp"]);
+        assert_eq!(servers["typescript"][1].args, ["--stdio"]);
+    }
+
+    #[test]
+    fn defaults_to_no_servers() {
+        assert!(servers("{}").is_empty());
+    }
+
+    #[test]
+    fn rejects_an_object_without_a_command() {
+        let json = r#"{"languageServers": {"rust": {"args": []}}}"#;
+        assert!(serde_json::from_str::<Config>(json).is_err());
+    }
 }
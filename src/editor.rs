@@ -1,21 +1,57 @@
+mod buffer;
+mod fold;
+mod hierarchy;
+mod highlight;
+mod language_response;
+mod list;
+mod prompt;
+mod semantic;
+mod server_request;
+mod signature;
+mod symbol;
+mod traffic;
+mod window;
+mod workspace_edit;
+
+use buffer::Buffer;
+use hierarchy::{Hierarchy, Tree};
+use list::List;
+use prompt::Prompt;
+use signature::Signature;
+use window::Progress;
+
+use anyhow::{anyhow, Result};
+use serde_json::json;
 use std::cmp;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::BufWriter;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process;
 
 use super::terminal;
-use crate::message::KeyPress;
+use crate::config::Config;
+use crate::language;
+use crate::language::{
+    CodeActionOrCommand, Location, Position, PublishDiagnosticsParams, Range, Registry, ServerId,
+};
+use crate::message::{Id, KeyPress, Notification, Response};
 
 pub struct Editor {
-    filepath: String,
-    lines: Vec<String>,
+    config: Config,
+    buffers: Vec<Buffer>,
+    current: usize,
     mode: Mode,
-    cursor: (usize, usize), // (line, column)
-    preserved_column: usize,
-    looking: (usize, usize), // Top left (line, column)
+    back_jumps: Vec<(usize, (usize, usize))>, // (buffer, cursor)
+    forward_jumps: Vec<(usize, (usize, usize))>,
+    list: Option<(List, ListAction)>,
+    prompt: Option<(Prompt, PromptAction)>,
+    signature: Option<Signature>, // Of the call the cursor is in
+    pending: HashMap<(ServerId, Id), Pending>,
+    message: Option<String>, // Shown on the bottom row until the next keypress
+    progress: Vec<Progress>,
+    logs: Vec<String>, // Of the language servers
 }
 
 enum Mode {
@@ -23,54 +59,167 @@ enum Mode {
     Command,
 }
 
+// What to do with the selected item of the list
+enum ListAction {
+    Jump(Vec<Location>),
+    CodeAction(ServerId, Vec<CodeActionOrCommand>),
+    MessageAction(ServerId, Id, Vec<serde_json::Value>), // Answer to window/showMessageRequest
+    View,                                                // Only to read
+    Traffic(Vec<String>),                                // Shows the whole message
+    Hierarchy(Tree),                                     // Jumps to the item
+}
+
+// What to do with the input of the prompt
+enum PromptAction {
+    Rename(ServerId, usize, Position), // (server, buffer, position)
+    WorkspaceSymbol(ServerId),
+}
+
+// What to do with the response of the language server
+enum Pending {
+    Goto(String), // Method
+    References,
+    PrepareRename(usize, Position), // (buffer, position)
+    Rename(usize, i32),             // (buffer, version)
+    CodeAction(usize, i32),
+    Formatting(usize, i32, bool), // (buffer, version, save after formatting)
+    RangeFormatting(usize, i32),
+    ExecuteCommand,
+    SignatureHelp(usize), // Buffer
+    DocumentSymbol(usize),
+    WorkspaceSymbol(String), // Query
+    InlayHint(usize, i32),
+    SemanticTokens(usize, i32),
+    DocumentHighlight(usize, i32, (usize, usize)), // (buffer, version, cursor)
+    PrepareHierarchy(Hierarchy),
+    Hierarchy, // Children of the node that asked
+    FoldingRange(usize, i32),
+    Initialize,
+}
+
 impl Editor {
-    pub fn new(filepath: String) -> Self {
+    pub fn new(filepath: String, config: Config) -> Self {
         let mut editor = Editor {
-            filepath,
-            lines: Vec::new(),
+            buffers: vec![Buffer::open(filepath, &config.languages).unwrap()],
+            config,
+            current: 0,
             mode: Mode::Normal,
-            cursor: (0, 0),
-            preserved_column: 0,
-            looking: (0, 0),
+            back_jumps: Vec::new(),
+            forward_jumps: Vec::new(),
+            list: None,
+            prompt: None,
+            signature: None,
+            pending: HashMap::new(),
+            message: None,
+            progress: Vec::new(),
+            logs: Vec::new(),
         };
         terminal::open();
-        let mut content = String::new();
-        for line in BufReader::new(fs::File::open(&editor.filepath).unwrap()).lines() {
-            let line = line.unwrap();
-            content += &line;
-            editor.lines.push(line);
-        }
         editor.refresh();
         editor
     }
 
-    pub async fn keypress_handler(&mut self, keypress: KeyPress) {
+    fn buffer(&self) -> &Buffer {
+        &self.buffers[self.current]
+    }
+
+    fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.buffers[self.current]
+    }
+
+    // Starts the servers for the file the editor was started with
+    pub async fn start_servers(&mut self, servers: &mut Registry) {
+        self.open_in_servers(servers, self.current).await;
+        self.update_inlay_hints(servers).await;
+        self.update_semantic_tokens(servers).await;
+        self.update_folding_ranges(servers).await;
+        self.refresh();
+    }
+
+    // Whether the server is for the language of the buffer
+    fn served(&self, client: &language::Client, index: usize) -> bool {
+        self.buffers[index].language.as_deref() == Some(client.language.as_str())
+    }
+
+    // The server for the current buffer supporting the capability
+    fn server<'a>(
+        &mut self,
+        servers: &'a mut Registry,
+        capability: &str,
+    ) -> Option<&'a mut language::Client> {
+        let client = servers.find(self.buffer().language.as_deref(), capability);
+        if client.is_none() {
+            self.message = Some(format!("NO LANGUAGE SERVER WITH {}", capability));
+        }
+        client
+    }
+
+    pub async fn keypress_handler(&mut self, keypress: KeyPress, servers: &mut Registry) {
+        self.message = None;
+        if self.prompt.is_some() {
+            self.prompt_keypress_handler(keypress, servers).await;
+            self.refresh();
+            return;
+        }
+        if self.list.is_some() {
+            self.list_keypress_handler(keypress, servers).await;
+            self.refresh();
+            return;
+        }
+
         match keypress {
             KeyPress::Character(character) => {
                 match self.mode {
                     Mode::Normal => {
-                        self.insert(character);
+                        self.buffer_mut().insert(character);
                     }
                     Mode::Command => {
                         match character {
-                            'h' => self.cursor_back(),
-                            'j' => self.cursor_down(),
-                            'k' => self.cursor_up(),
-                            'l' => self.cursor_forward(),
-                            'p' => self.page_forward(),
-                            'P' => self.page_back(),
+                            'h' => self.buffer_mut().cursor_back(),
+                            'j' => self.buffer_mut().cursor_down(),
+                            'k' => self.buffer_mut().cursor_up(),
+                            'l' => self.buffer_mut().cursor_forward(),
+                            'p' => self.buffer_mut().page_forward(),
+                            'P' => self.buffer_mut().page_back(),
                             'w' => self.word_forward(),
                             'W' => self.word_back(),
+                            'd' => self.goto(servers, "textDocument/definition").await,
+                            'D' => self.goto(servers, "textDocument/declaration").await,
+                            't' => self.goto(servers, "textDocument/typeDefinition").await,
+                            'I' => self.goto(servers, "textDocument/implementation").await,
+                            'r' => self.references(servers).await,
+                            'R' => self.rename(servers).await,
+                            'a' => self.code_action(servers).await,
+                            'v' => self.buffer_mut().toggle_mark(),
+                            'f' => self.format(servers, false).await,
+                            'F' => self.format_selection(servers).await,
+                            'o' => self.jump_back(),
+                            'i' => self.jump_forward(),
+                            's' => self.document_symbols(servers).await,
+                            'S' => self.workspace_symbols(servers).await,
+                            'X' => self.restart_servers(servers).await,
+                            'L' => self.show_log(),
+                            'T' => self.toggle_trace(servers).await,
+                            '[' => self.buffer_mut().fold(),
+                            ']' => self.buffer_mut().unfold(),
+                            'z' => self.buffer_mut().toggle_fold(),
+                            '{' => self.buffer_mut().fold_all(),
+                            '}' => self.buffer_mut().unfold_all(),
+                            'c' => self.hierarchy(servers, Hierarchy::Callers).await,
+                            'C' => self.hierarchy(servers, Hierarchy::Callees).await,
+                            'y' => self.hierarchy(servers, Hierarchy::Subtypes).await,
+                            'Y' => self.hierarchy(servers, Hierarchy::Supertypes).await,
+                            'M' => self.show_traffic(servers),
                             _ => (),
                         };
                     }
                 };
             }
             KeyPress::CR => {
-                self.nextline();
+                self.buffer_mut().nextline();
             }
             KeyPress::Delete => {
-                self.backspace();
+                self.buffer_mut().backspace();
             }
 
             KeyPress::Control('F') => {
@@ -80,166 +229,904 @@ impl Editor {
                 };
             }
             KeyPress::Control('S') => {
-                self.save();
+                let language = self.buffer().language.clone();
+                let format_on_save = language
+                    .as_ref()
+                    .and_then(|language| self.config.languages.get(language))
+                    .is_some_and(|language| language.format_on_save);
+                if format_on_save
+                    && servers
+                        .find(language.as_deref(), "documentFormattingProvider")
+                        .is_some()
+                {
+                    self.format(servers, true).await; // Saved when formatted
+                } else {
+                    self.save(self.current);
+                }
             }
             KeyPress::Control('Q') => {
                 // TODO: Check saved or not
                 terminal::close();
                 process::exit(0);
             }
-            KeyPress::CursorUp => self.cursor_up(),
-            KeyPress::CursorDown => self.cursor_down(),
-            KeyPress::CursorForward => self.cursor_forward(),
-            KeyPress::CursorBack => self.cursor_back(),
+            KeyPress::CursorUp => self.buffer_mut().cursor_up(),
+            KeyPress::CursorDown => self.buffer_mut().cursor_down(),
+            KeyPress::CursorForward => self.buffer_mut().cursor_forward(),
+            KeyPress::CursorBack => self.buffer_mut().cursor_back(),
             _ => {
                 panic!("UNSUPPORTED KEY EVENT");
             }
         }
 
+        self.update_signature_help(servers, keypress).await;
+        self.update_inlay_hints(servers).await;
+        self.update_semantic_tokens(servers).await;
+        self.update_folding_ranges(servers).await;
+        self.update_document_highlights(servers).await;
         self.refresh();
     }
 
-    fn insert(&mut self, ch: char) {
-        if self.lines.len() <= self.cursor.0 {
-            self.lines.resize(self.cursor.0 + 1, Default::default());
+    // For the visible lines, asked again whenever they or the content change
+    async fn update_inlay_hints(&mut self, servers: &mut Registry) {
+        let rows = terminal::size().0;
+        let buffer = &mut self.buffers[self.current];
+        buffer.scroll(rows);
+        let visible = buffer.visible_lines(rows);
+        let lines = (
+            buffer.looking.0,
+            visible.last().map_or(buffer.looking.0, |line| line + 1),
+        );
+        let asked = Some((buffer.version, lines));
+        if buffer.inlay_hints_asked == asked {
+            return;
         }
-        self.lines[self.cursor.0].insert(self.cursor.1, ch);
-        self.cursor.1 += 1;
+        let language = buffer.language.clone();
+        if servers
+            .find(language.as_deref(), "inlayHintProvider")
+            .is_none()
+        {
+            return;
+        }
+
+        self.sync(servers).await;
+        let Some(client) = servers.find(language.as_deref(), "inlayHintProvider") else {
+            return;
+        };
+        let buffer = &mut self.buffers[self.current];
+        buffer.inlay_hints_asked = asked;
+        let range = Range {
+            start: Position {
+                line: lines.0 as u32,
+                character: 0,
+            },
+            end: Position {
+                line: lines.1 as u32,
+                character: 0,
+            },
+        };
+        let pending = Pending::InlayHint(self.current, buffer.version);
+        let id = client.inlay_hint(&buffer.uri, range).await;
+        self.wait(client.id, id, pending);
     }
 
-    fn backspace(&mut self) {
-        if self.cursor.1 == 0 {
-            if self.cursor.0 == 0 {
-                return;
+    // Asked on a trigger character, then again on every key while the box is shown,
+    // until the server answers that the cursor left the call
+    async fn update_signature_help(&mut self, servers: &mut Registry, keypress: KeyPress) {
+        if !matches!(self.mode, Mode::Normal) {
+            self.signature = None;
+            return;
+        }
+        let shown = self.signature.is_some();
+        let language = self.buffer().language.clone();
+        let Some(client) = servers.find(language.as_deref(), "signatureHelpProvider") else {
+            self.signature = None;
+            return;
+        };
+        let trigger = match keypress {
+            KeyPress::Character(character)
+                if client.signature_help_triggers(shown).contains(&character) =>
+            {
+                Some(character)
             }
-            let prev_line_len = self.lines[self.cursor.0 - 1].len();
-            self.lines[self.cursor.0 - 1] = format!(
-                "{}{}",
-                self.lines[self.cursor.0 - 1],
-                self.lines[self.cursor.0]
-            );
-            for i in self.cursor.0..(self.lines.len() - 1) {
-                self.lines[i] = self.lines[i + 1].clone();
+            _ => None,
+        };
+        if trigger.is_none() && !shown {
+            return;
+        }
+
+        self.sync(servers).await;
+        let Some(client) = servers.find(language.as_deref(), "signatureHelpProvider") else {
+            return;
+        };
+        let context = match trigger {
+            Some(character) => json!({
+                "triggerKind": 2, // TriggerCharacter
+                "triggerCharacter": character.to_string(),
+                "isRetrigger": shown,
+            }),
+            None => json!({ "triggerKind": 3, "isRetrigger": true }), // ContentChange
+        };
+        let buffer = self.buffer();
+        let id = client
+            .signature_help(&buffer.uri, buffer.position(), context)
+            .await;
+        self.wait(client.id, id, Pending::SignatureHelp(self.current));
+    }
+
+    async fn list_keypress_handler(&mut self, keypress: KeyPress, servers: &mut Registry) {
+        let Some((list, _)) = &mut self.list else {
+            return;
+        };
+        match keypress {
+            KeyPress::Character('j') | KeyPress::CursorDown => list.cursor_down(),
+            KeyPress::Character('k') | KeyPress::CursorUp => list.cursor_up(),
+            KeyPress::Character('p') => list.page_forward(),
+            KeyPress::Character('P') => list.page_back(),
+            KeyPress::Character('l') | KeyPress::CursorForward => {
+                let row = list.selected;
+                self.expand(servers, row).await;
+            }
+            KeyPress::Character('h') | KeyPress::CursorBack => {
+                let row = list.selected;
+                self.collapse(row);
+            }
+            KeyPress::Character('q') | KeyPress::Control('C') => {
+                if let Some((_, ListAction::MessageAction(server, id, _))) = self.list.take() {
+                    if let Some(client) = servers.get(server) {
+                        let result = client
+                            .respond(Response::new(id, serde_json::Value::Null))
+                            .await;
+                        self.report(result);
+                    }
+                }
+            }
+            KeyPress::CR => {
+                if let Some((list, action)) = self.list.take() {
+                    self.select(servers, list.selected, action).await;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    async fn select(&mut self, servers: &mut Registry, index: usize, action: ListAction) {
+        match action {
+            ListAction::Jump(locations) => {
+                if let Some(location) = locations.get(index) {
+                    self.jump(servers, location).await;
+                }
+            }
+            ListAction::CodeAction(server, mut actions) => {
+                if index < actions.len() {
+                    self.apply_code_action(servers, server, actions.swap_remove(index))
+                        .await;
+                }
             }
-            self.lines.pop();
-            self.cursor.0 -= 1;
-            self.cursor.1 = prev_line_len;
+            ListAction::MessageAction(server, id, mut actions) => {
+                let action = if index < actions.len() {
+                    actions.swap_remove(index)
+                } else {
+                    serde_json::Value::Null
+                };
+                if let Some(client) = servers.get(server) {
+                    let result = client.respond(Response::new(id, action)).await;
+                    self.report(result);
+                }
+            }
+            ListAction::View => (),
+            ListAction::Hierarchy(tree) => {
+                if let Some(location) = tree.location(index) {
+                    self.jump(servers, &location.clone()).await;
+                }
+            }
+            ListAction::Traffic(lines) => {
+                if let Some(line) = lines.get(index) {
+                    self.show_message_of(line);
+                }
+            }
+        }
+    }
+
+    pub fn notification_handler(
+        &mut self,
+        server: ServerId,
+        notification: Notification,
+        servers: &mut Registry,
+    ) {
+        match notification.method.as_str() {
+            "textDocument/publishDiagnostics" => {
+                let Ok(params) =
+                    serde_json::from_value::<PublishDiagnosticsParams>(notification.params)
+                else {
+                    return;
+                };
+                // Each server keeps its own, so that one does not wipe out the others
+                if let Some(index) = language::uri_to_path(&params.uri)
+                    .and_then(|filepath| self.find_buffer(&filepath))
+                {
+                    self.buffers[index]
+                        .diagnostics
+                        .insert(server, params.diagnostics);
+                }
+            }
+            "$/progress" => self.progress(server, &notification.params, servers),
+            "window/showMessage" => self.show_message(server, &notification.params, servers),
+            "window/logMessage" | "$/logTrace" => {
+                self.log_message(server, &notification.params, servers)
+            }
+            _ => (),
+        }
+        self.refresh();
+    }
+
+    async fn prompt_keypress_handler(&mut self, keypress: KeyPress, servers: &mut Registry) {
+        let Some((prompt, _)) = &mut self.prompt else {
             return;
+        };
+        match keypress {
+            KeyPress::Character(character) => {
+                prompt.insert(character);
+                self.query_workspace_symbols(servers).await;
+            }
+            KeyPress::Delete | KeyPress::BS => {
+                prompt.backspace();
+                self.query_workspace_symbols(servers).await;
+            }
+            // The list of the results under the prompt, if any
+            KeyPress::CursorDown => {
+                if let Some((list, _)) = &mut self.list {
+                    list.cursor_down();
+                }
+            }
+            KeyPress::CursorUp => {
+                if let Some((list, _)) = &mut self.list {
+                    list.cursor_up();
+                }
+            }
+            KeyPress::Control('C') => {
+                if let Some((_, PromptAction::WorkspaceSymbol(_))) = self.prompt.take() {
+                    // Not a list still to be answered to the server
+                    if !matches!(self.list, Some((_, ListAction::MessageAction(..)))) {
+                        self.list = None;
+                    }
+                }
+            }
+            KeyPress::CR => {
+                if let Some((prompt, action)) = self.prompt.take() {
+                    self.confirm(servers, prompt.input, action).await;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    async fn confirm(&mut self, servers: &mut Registry, input: String, action: PromptAction) {
+        match action {
+            PromptAction::Rename(server, index, position) => {
+                let Some(client) = servers.get(server) else {
+                    return;
+                };
+                let buffer = &self.buffers[index];
+                let pending = Pending::Rename(index, buffer.version);
+                let id = client.rename(&buffer.uri, position, &input).await;
+                self.wait(server, id, pending);
+            }
+            PromptAction::WorkspaceSymbol(_) => {
+                if let Some((list, action)) = self.list.take() {
+                    self.select(servers, list.selected, action).await;
+                }
+            }
         }
-        self.lines[self.cursor.0].remove(self.cursor.1 - 1);
-        self.cursor.1 -= 1;
     }
 
-    fn nextline(&mut self) {
-        self.lines.push(String::new());
-        for i in ((self.cursor.0 + 1)..self.lines.len()).rev() {
-            self.lines[i] = self.lines[i - 1].clone();
+    fn word_forward(&mut self) {
+        todo!()
+    }
+
+    fn word_back(&mut self) {
+        todo!()
+    }
+
+    // Send the latest content before asking the language server about it
+    async fn sync(&mut self, servers: &mut Registry) {
+        self.sync_buffer(servers, self.current).await;
+    }
+
+    async fn sync_buffer(&mut self, servers: &mut Registry, index: usize) {
+        let buffer = &self.buffers[index];
+        let Some(language) = &buffer.language else {
+            return;
+        };
+        if buffer.synced {
+            return;
+        }
+        let mut result = Ok(());
+        for client in servers.clients(language) {
+            let changed = client
+                .did_change(&buffer.uri, buffer.version, &buffer.text())
+                .await;
+            if changed.is_err() {
+                result = changed;
+            }
+        }
+        if self.report(result).is_some() {
+            self.buffers[index].synced = true;
         }
-        match self.lines[self.cursor.0].char_indices().nth(self.cursor.1) {
-            Some((pos, _)) => {
-                self.lines[self.cursor.0 + 1] = self.lines[self.cursor.0][pos..].to_string();
-                self.lines[self.cursor.0] = self.lines[self.cursor.0][..pos].to_string();
+    }
+
+    // Opens the buffer in the servers of its language, starting them the first time
+    async fn open_in_servers(&mut self, servers: &mut Registry, index: usize) {
+        let Some(language) = self.buffers[index].language.clone() else {
+            return;
+        };
+        if servers.started(&language) {
+            for client in servers.clients(&language) {
+                let result = self.open_document(client, index).await;
+                self.report(result);
             }
-            None => {
-                self.lines[self.cursor.0 + 1] = String::new();
+            return;
+        }
+
+        let filepath = PathBuf::from(&self.buffers[index].filepath);
+        // The documents are opened once the servers answer initialize
+        for started in servers.start(&language, &filepath).await {
+            match started {
+                Ok((server, id)) => self.wait(server, Ok(id), Pending::Initialize),
+                Err(err) => {
+                    self.report::<()>(Err(err));
+                }
             }
+        }
+    }
+
+    // Every buffer the server is for
+    async fn open_documents(&mut self, client: &mut language::Client) {
+        for index in 0..self.buffers.len() {
+            let result = self.open_document(client, index).await;
+            self.report(result);
+        }
+    }
+
+    // Finishes the handshake, then the server learns about the buffers
+    pub(super) async fn server_initialized(
+        &mut self,
+        servers: &mut Registry,
+        server: ServerId,
+        result: serde_json::Value,
+    ) {
+        let Some(client) = servers.get(server) else {
+            return;
         };
-        self.cursor.0 += 1;
-        self.cursor.1 = 0;
+        let initialized = client.initialized(result).await;
+        if self.report(initialized).is_none() {
+            return;
+        }
+        self.open_documents(client).await;
+        self.update_inlay_hints(servers).await;
+        self.update_semantic_tokens(servers).await;
+        self.update_folding_ranges(servers).await;
     }
 
-    fn cursor_up(&mut self) {
-        if self.cursor.0 > 0 {
-            self.cursor.0 -= 1;
+    async fn open_document(&self, client: &mut language::Client, index: usize) -> Result<()> {
+        if !self.served(client, index) {
+            return Ok(());
         }
-        self.cursor.1 = cmp::min(self.preserved_column, self.lines[self.cursor.0].len());
+        let buffer = &self.buffers[index];
+        client
+            .did_open(&buffer.uri, buffer.version, &buffer.text())
+            .await
     }
 
-    fn cursor_down(&mut self) {
-        if self.cursor.0 + 1 < self.lines.len() {
-            self.cursor.0 += 1;
+    async fn close_document(&self, servers: &mut Registry, index: usize) -> Result<()> {
+        let buffer = &self.buffers[index];
+        let Some(language) = &buffer.language else {
+            return Ok(());
+        };
+        for client in servers.clients(language) {
+            client.did_close(&buffer.uri).await?;
         }
-        self.cursor.1 = cmp::min(self.preserved_column, self.lines[self.cursor.0].len());
+        Ok(())
+    }
+
+    pub fn server_exit_handler(
+        &mut self,
+        server: ServerId,
+        pid: u32,
+        status: String,
+        servers: &mut Registry,
+    ) {
+        // Exits of replaced servers are expected
+        let Some(client) = servers.get(server).filter(|client| client.pid() == pid) else {
+            return;
+        };
+        self.progress.retain(|progress| progress.server != server);
+        self.message = Some(match client.schedule_restart() {
+            Some(delay) => format!(
+                "LANGUAGE SERVER FOR {} EXITED ({}), RESTARTING IN {}s",
+                client.language,
+                status,
+                delay.as_secs()
+            ),
+            None => format!(
+                "LANGUAGE SERVER FOR {} EXITED ({}), RESTART WITH X",
+                client.language, status
+            ),
+        });
+        self.refresh();
     }
 
-    fn cursor_forward(&mut self) {
-        if self.cursor.1 < self.lines[self.cursor.0].len() {
-            self.cursor.1 += 1;
-            self.preserved_column = self.cursor.1;
+    pub async fn server_restart_handler(
+        &mut self,
+        server: ServerId,
+        pid: u32,
+        servers: &mut Registry,
+    ) {
+        let Some(client) = servers.get(server).filter(|client| client.pid() == pid) else {
+            return;
+        };
+        let result = client.restart().await;
+        self.reopen_documents(client.id, result);
+    }
+
+    // Every server for the current buffer
+    async fn restart_servers(&mut self, servers: &mut Registry) {
+        let Some(language) = self.buffer().language.clone() else {
+            return;
+        };
+        let mut restarted = false;
+        for client in servers.clients(&language) {
+            let result = client.restart_manually().await;
+            self.reopen_documents(client.id, result);
+            restarted = true;
+        }
+        if !restarted {
+            self.message = Some(format!("NO LANGUAGE SERVER FOR {}", language));
         }
     }
 
-    fn cursor_back(&mut self) {
-        if self.cursor.1 > 0 {
-            self.cursor.1 -= 1;
-            self.preserved_column = self.cursor.1;
+    // The new server knows nothing about the buffers, they are opened once it is initialized
+    fn reopen_documents(&mut self, server: ServerId, restarted: Result<Id>) {
+        // The saves waiting for the old server to format happen unformatted
+        let mut unsaved = Vec::new();
+        self.pending.retain(|(pending, _), waiting| {
+            if *pending != server {
+                return true;
+            }
+            if let Pending::Formatting(index, _, true) = waiting {
+                unsaved.push(*index);
+            }
+            false
+        });
+        for index in unsaved {
+            self.save(index);
+        }
+        self.progress.retain(|progress| progress.server != server);
+        for buffer in &mut self.buffers {
+            buffer.diagnostics.remove(&server);
+            buffer.inlay_hints_asked = None;
+            buffer.semantic_tokens = None;
+            buffer.semantic_tokens_asked = None;
+            buffer.occurrences_asked = None;
+            buffer.folding_ranges_asked = None;
         }
+        self.wait(server, restarted, Pending::Initialize);
+        self.refresh();
     }
 
-    fn page_forward(&mut self) {
-        let window_size = terminal::size();
-        self.cursor.0 = cmp::min(self.cursor.0 + window_size.0, self.lines.len() - 1);
-        self.looking.0 = cmp::min(
-            self.looking.0 + window_size.0,
-            self.lines.len() - window_size.0,
-        );
+    // Only on exit, so failures do not matter
+    pub async fn close_documents(&self, servers: &mut Registry) {
+        for client in servers.all() {
+            for (index, buffer) in self.buffers.iter().enumerate() {
+                if self.served(client, index) {
+                    let _ = client.did_close(&buffer.uri).await;
+                }
+            }
+        }
     }
 
-    fn page_back(&mut self) {
-        let window_size = terminal::size();
-        if self.cursor.0 > window_size.0 {
-            self.cursor.0 -= window_size.0;
-            if self.looking.0 > window_size.0 {
-                self.looking.0 -= window_size.0;
-            } else {
-                self.looking.0 = 0;
+    // A failure, such as a read-only file, is shown instead
+    fn save(&mut self, index: usize) {
+        let buffer = &self.buffers[index];
+        let result = buffer
+            .save()
+            .map_err(|err| anyhow!("NOT SAVED {}: {}", buffer.filepath, err));
+        self.report(result);
+    }
+
+    // Shows the error on the bottom row instead of failing
+    fn report<T>(&mut self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.message = Some(err.to_string());
+                None
             }
+        }
+    }
+
+    pub fn show_error(&mut self, err: anyhow::Error) {
+        self.report::<()>(Err(err));
+        self.refresh();
+    }
+
+    fn find_buffer(&self, filepath: &str) -> Option<usize> {
+        let canonical = fs::canonicalize(filepath).ok()?;
+        self.buffers
+            .iter()
+            .position(|buffer| fs::canonicalize(&buffer.filepath).ok().as_ref() == Some(&canonical))
+    }
+
+    // Returns the index of the buffer, opening the file if it is not opened yet
+    async fn open(&mut self, servers: &mut Registry, filepath: &str) -> Option<usize> {
+        if let Some(index) = self.find_buffer(filepath) {
+            return Some(index);
+        }
+        let buffer = Buffer::open(filepath.to_string(), &self.config.languages);
+        let buffer = self.report(buffer.map_err(anyhow::Error::from))?;
+        self.buffers.push(buffer);
+        let index = self.buffers.len() - 1;
+        self.open_in_servers(servers, index).await;
+        Some(index)
+    }
+
+    // Remembers what to do when the response comes
+    fn wait(&mut self, server: ServerId, id: Result<Id>, pending: Pending) {
+        if let Some(id) = self.report(id) {
+            self.pending.insert((server, id), pending);
+        }
+    }
+
+    // The capability is named after the method, such as definitionProvider
+    async fn goto(&mut self, servers: &mut Registry, method: &str) {
+        self.sync(servers).await;
+        let capability = format!("{}Provider", method.trim_start_matches("textDocument/"));
+        let Some(client) = self.server(servers, &capability) else {
+            return;
+        };
+        let buffer = self.buffer();
+        let id = client.goto(method, &buffer.uri, buffer.position()).await;
+        self.wait(client.id, id, Pending::Goto(method.to_string()));
+    }
+
+    async fn references(&mut self, servers: &mut Registry) {
+        self.sync(servers).await;
+        let Some(client) = self.server(servers, "referencesProvider") else {
+            return;
+        };
+        let buffer = self.buffer();
+        let id = client.references(&buffer.uri, buffer.position()).await;
+        self.wait(client.id, id, Pending::References);
+    }
+
+    async fn rename(&mut self, servers: &mut Registry) {
+        self.sync(servers).await;
+        let Some(client) = self.server(servers, "renameProvider") else {
+            return;
+        };
+        let buffer = self.buffer();
+        let position = buffer.position();
+        if client.capabilities["renameProvider"]["prepareProvider"] == true {
+            let id = client.prepare_rename(&buffer.uri, position).await;
+            self.wait(
+                client.id,
+                id,
+                Pending::PrepareRename(self.current, position),
+            );
         } else {
-            self.cursor.0 = 0;
-            self.looking.0 = 0;
+            let placeholder = buffer.word();
+            self.prompt_rename(client.id, self.current, position, placeholder);
         }
     }
 
-    fn word_forward(&mut self) {
-        todo!()
+    fn prompt_rename(
+        &mut self,
+        server: ServerId,
+        index: usize,
+        position: Position,
+        placeholder: String,
+    ) {
+        let prompt = Prompt::new("Rename".to_string(), placeholder);
+        self.prompt = Some((prompt, PromptAction::Rename(server, index, position)));
     }
 
-    fn word_back(&mut self) {
-        todo!()
+    async fn code_action(&mut self, servers: &mut Registry) {
+        self.sync(servers).await;
+        let Some(client) = self.server(servers, "codeActionProvider") else {
+            return;
+        };
+        let buffer = self.buffer();
+        let range = buffer.selection();
+        let diagnostics = buffer.diagnostics_in(client.id, range);
+        let pending = Pending::CodeAction(self.current, buffer.version);
+        let id = client.code_action(&buffer.uri, range, &diagnostics).await;
+        self.wait(client.id, id, pending);
+        self.buffer_mut().mark = None;
     }
 
-    fn refresh(&mut self) {
-        let window_size = terminal::size();
-        if self.cursor.0 < self.looking.0 {
-            self.looking.0 = self.cursor.0;
+    // The edit first, then the command, by the server which offered the action
+    async fn apply_code_action(
+        &mut self,
+        servers: &mut Registry,
+        server: ServerId,
+        action: CodeActionOrCommand,
+    ) -> Option<()> {
+        let command = match action {
+            CodeActionOrCommand::Command(command) => Some(command),
+            CodeActionOrCommand::CodeAction(action) => {
+                if let Some(edit) = action.edit {
+                    let result = self.apply_workspace_edit(servers, edit).await;
+                    self.report(result)?;
+                }
+                action.command
+            }
+        };
+        if let Some(command) = command {
+            let id = servers.get(server)?.execute_command(&command).await;
+            self.wait(server, id, Pending::ExecuteCommand);
+        }
+        Some(())
+    }
+
+    async fn format(&mut self, servers: &mut Registry, save: bool) {
+        self.sync(servers).await;
+        let Some(client) = self.server(servers, "documentFormattingProvider") else {
+            return;
+        };
+        let buffer = self.buffer();
+        let pending = Pending::Formatting(self.current, buffer.version, save);
+        let id = client.formatting(&buffer.uri).await;
+        if id.is_err() && save {
+            self.save(self.current);
+        }
+        self.wait(client.id, id, pending);
+    }
+
+    // The selection, or the cursor line without the mark
+    async fn format_selection(&mut self, servers: &mut Registry) {
+        self.sync(servers).await;
+        let Some(client) = self.server(servers, "documentRangeFormattingProvider") else {
+            return;
+        };
+        let buffer = self.buffer();
+        let mut range = buffer.selection();
+        if buffer.mark.is_none() {
+            range.start.character = 0;
+            range.end = Position {
+                line: range.end.line + 1,
+                character: 0,
+            };
+        }
+        let pending = Pending::RangeFormatting(self.current, buffer.version);
+        let id = client.range_formatting(&buffer.uri, range).await;
+        self.wait(client.id, id, pending);
+        self.buffer_mut().mark = None;
+    }
+
+    // The server waits for the answer to window/showMessageRequest, so that list stays open
+    fn show_list(&mut self, list: List, action: ListAction) {
+        if let Some((_, ListAction::MessageAction(..))) = &self.list {
+            return;
+        }
+        self.list = Some((list, action));
+    }
+
+    fn show_locations(&mut self, title: String, locations: Vec<Location>) {
+        let mut files = HashMap::new();
+        let labels = locations
+            .iter()
+            .map(|location| self.location_label(location, &mut files))
+            .collect();
+        self.show_list(List::new(title, labels), ListAction::Jump(locations));
+    }
+
+    // "file:line: preview", with the files not opened read once per list
+    fn location_label(
+        &self,
+        location: &Location,
+        files: &mut HashMap<String, Vec<String>>,
+    ) -> String {
+        let filepath = language::uri_to_path(&location.uri).unwrap_or(location.uri.clone());
+        let line = location.range.start.line as usize;
+        let lines = match self.find_buffer(&filepath) {
+            Some(index) => &self.buffers[index].lines,
+            None => files.entry(filepath).or_insert_with_key(|filepath| {
+                fs::read_to_string(filepath)
+                    .map(|content| content.lines().map(String::from).collect())
+                    .unwrap_or_default()
+            }),
+        };
+        let preview = lines.get(line).map_or("", |line| line.trim());
+        format!("{}: {}", self.location_path(location), preview)
+    }
+
+    // "file:line", relative to the current directory
+    fn location_path(&self, location: &Location) -> String {
+        let filepath = language::uri_to_path(&location.uri).unwrap_or(location.uri.clone());
+        let filepath = match env::current_dir() {
+            Ok(dir) => Path::new(&filepath)
+                .strip_prefix(dir)
+                .map_or(filepath.clone(), |path| path.display().to_string()),
+            Err(_) => filepath,
+        };
+        format!("{}:{}", filepath, location.range.start.line + 1)
+    }
+
+    async fn jump(&mut self, servers: &mut Registry, location: &Location) {
+        let Some(filepath) = language::uri_to_path(&location.uri) else {
+            return;
+        };
+        let Some(index) = self.open(servers, &filepath).await else {
+            return;
+        };
+        self.back_jumps.push((self.current, self.buffer().cursor));
+        self.forward_jumps.clear();
+        self.current = index;
+        let cursor = self.buffer().cursor_at(location.range.start);
+        self.buffer_mut().set_cursor(cursor);
+    }
+
+    fn jump_back(&mut self) {
+        if let Some((index, cursor)) = self.back_jumps.pop() {
+            self.forward_jumps
+                .push((self.current, self.buffer().cursor));
+            self.current = index;
+            self.buffer_mut().set_cursor(cursor);
         }
-        if self.cursor.0 >= self.looking.0 + window_size.0 {
-            self.looking.0 = self.cursor.0 - window_size.0 + 1;
+    }
+
+    fn jump_forward(&mut self) {
+        if let Some((index, cursor)) = self.forward_jumps.pop() {
+            self.back_jumps.push((self.current, self.buffer().cursor));
+            self.current = index;
+            self.buffer_mut().set_cursor(cursor);
+        }
+    }
+
+    fn refresh(&mut self) {
+        if let Some((list, _)) = &mut self.list {
+            list.refresh();
+            if let Some((prompt, _)) = &self.prompt {
+                prompt.refresh();
+            }
+            while std::io::stdout().flush().is_err() {}
+            return;
         }
+
+        let window_size = terminal::size();
+        let buffer = &mut self.buffers[self.current];
+        buffer.scroll(window_size.0);
+        let selection = buffer
+            .mark
+            .map(|mark| (cmp::min(mark, buffer.cursor), cmp::max(mark, buffer.cursor)));
+        let hints = buffer.inlay_hint_texts();
+        let visible = buffer.visible_lines(window_size.0);
         for row in 0..window_size.0 {
             terminal::move_cursor(row + 1, 1);
-            if self.looking.0 + row >= self.lines.len() {
-                break;
-            }
             terminal::clear_line();
-            print!("{}", self.lines[self.looking.0 + row]);
-        }
-        terminal::move_cursor(self.cursor.0 - self.looking.0 + 1, self.cursor.1 + 1);
-        loop {
-            match std::io::stdout().flush() {
-                Ok(_) => {
-                    break;
+            let Some(&index) = visible.get(row) else {
+                continue;
+            };
+            let line = &buffer.lines[index];
+            let selected = match selection {
+                Some((start, end)) if start.0 <= index && index <= end.0 => {
+                    let from = if start.0 == index {
+                        cmp::min(start.1, line.len())
+                    } else {
+                        0
+                    };
+                    let to = if end.0 == index {
+                        cmp::min(end.1, line.len())
+                    } else {
+                        line.len()
+                    };
+                    Some((from, to))
                 }
-                Err(_) => {}
+                _ => None,
+            };
+            draw_line(
+                line,
+                selected,
+                hints.get(&index).map_or(&[], Vec::as_slice),
+                buffer.highlights.get(&index).map_or(&[], Vec::as_slice),
+                buffer.occurrences.get(&index).map_or(&[], Vec::as_slice),
+            );
+            if let Some((start, end)) = buffer.folded_at(index) {
+                draw_hint(&format!(" ... {} lines", end - start));
             }
         }
+        // One cell per character before the cursor, whose column counts bytes
+        let column = buffer.lines.get(buffer.cursor.0).map_or(0, |line| {
+            line.char_indices()
+                .take_while(|(index, _)| *index < buffer.cursor.1)
+                .count()
+        });
+        // The virtual text before the cursor pushes it to the right
+        let shift: usize = hints.get(&buffer.cursor.0).map_or(0, |hints| {
+            hints
+                .iter()
+                .filter(|(column, _)| *column <= buffer.cursor.1)
+                .map(|(_, text)| text.chars().count())
+                .sum()
+        });
+        let row = visible
+            .iter()
+            .position(|line| *line == buffer.cursor.0)
+            .unwrap_or_default();
+        let cursor = (row + 1, column + shift + 1);
+        if let Some(signature) = &self.signature {
+            signature.refresh(cursor);
+        }
+        if let Some(message) = &self.message {
+            terminal::move_cursor(window_size.0, 1);
+            terminal::clear_line();
+            terminal::reverse_video();
+            print!("{}", list::truncate(message, window_size.1));
+            terminal::reset_style();
+        } else if let Some(status) = self.status() {
+            terminal::move_cursor(window_size.0, 1);
+            terminal::clear_line();
+            terminal::faint();
+            print!("{}", list::truncate(&status, window_size.1));
+            terminal::reset_style();
+        }
+        terminal::move_cursor(cursor.0, cursor.1);
+        if let Some((prompt, _)) = &self.prompt {
+            prompt.refresh();
+        }
+        while std::io::stdout().flush().is_err() {}
     }
+}
 
-    fn save(&mut self) {
-        let mut writer = BufWriter::new(fs::File::create(&self.filepath).unwrap());
-        for line in &self.lines {
-            writer.write(line.as_bytes()).unwrap();
-            writer.write("\n".as_bytes()).unwrap();
+// The line in the colors of the semantic tokens, on the background of the occurrences,
+// with the selection reversed and the inlay hints faint between the characters
+fn draw_line(
+    line: &str,
+    selected: Option<(usize, usize)>,
+    hints: &[(usize, String)],
+    highlights: &[(usize, usize, u8)],
+    occurrences: &[(usize, usize, u8)],
+) {
+    let mut hints = hints.iter().peekable();
+    let mut style = None; // (color, background, reversed) since the last reset
+    for (column, ch) in line.char_indices() {
+        while let Some((_, text)) = hints.next_if(|(hint_column, _)| *hint_column <= column) {
+            draw_hint(text);
+            style = None;
+        }
+        let color = color_at(highlights, column);
+        let background = color_at(occurrences, column);
+        let reversed = selected.is_some_and(|(from, to)| from <= column && column < to);
+        if style != Some((color, background, reversed)) {
+            terminal::reset_style();
+            if let Some(color) = color {
+                terminal::foreground(color);
+            }
+            if let Some(background) = background {
+                terminal::background(background);
+            }
+            if reversed {
+                terminal::reverse_video();
+            }
+            style = Some((color, background, reversed));
         }
+        print!("{}", ch);
+    }
+    terminal::reset_style();
+    for (_, text) in hints {
+        draw_hint(text);
     }
 }
+
+fn color_at(ranges: &[(usize, usize, u8)], column: usize) -> Option<u8> {
+    ranges
+        .iter()
+        .find(|(start, end, _)| *start <= column && column < *end)
+        .map(|(_, _, color)| *color)
+}
+
+fn draw_hint(text: &str) {
+    terminal::reset_style();
+    terminal::faint();
+    print!("{}", text);
+    terminal::reset_style();
+}
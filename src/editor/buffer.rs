@@ -0,0 +1,489 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+
+use crate::config::LanguageConfig;
+use crate::language;
+use crate::language::{Diagnostic, InlayHint, Position, Range, ServerId, TextEdit};
+use crate::terminal;
+
+pub struct Buffer {
+    pub filepath: String,
+    pub uri: String,
+    pub language: Option<String>, // LSP language ID
+    pub lines: Vec<String>,
+    pub cursor: (usize, usize), // (line, column)
+    pub preserved_column: usize,
+    pub looking: (usize, usize),      // Top left (line, column)
+    pub mark: Option<(usize, usize)>, // The other end of the selection
+    pub version: i32,
+    pub synced: bool, // Whether the language server knows the latest content
+    pub diagnostics: HashMap<ServerId, Vec<Diagnostic>>,
+    pub inlay_hints: (i32, Vec<InlayHint>), // (version, hints)
+    pub inlay_hints_asked: Option<(i32, (usize, usize))>, // (version, lines)
+    pub highlights: HashMap<usize, Vec<(usize, usize, u8)>>, // Line to (start, end, color)
+    pub semantic_tokens: Option<(ServerId, String, Vec<u32>)>, // (server, result ID, data) for deltas
+    pub semantic_tokens_asked: Option<i32>,                    // Version
+    pub occurrences: HashMap<usize, Vec<(usize, usize, u8)>>,  // Of the symbol under the cursor
+    pub occurrences_asked: Option<(i32, (usize, usize))>,      // (version, cursor)
+    pub folding_ranges: (i32, Vec<(usize, usize)>),            // (version, (start, end) lines)
+    pub folding_ranges_asked: Option<i32>,                     // Version
+    pub folded: Vec<(usize, usize)>,                           // Closed folds, possibly nested
+}
+
+impl Buffer {
+    pub fn open(filepath: String, languages: &HashMap<String, LanguageConfig>) -> io::Result<Self> {
+        let mut lines = Vec::new();
+        for line in BufReader::new(fs::File::open(&filepath)?).lines() {
+            lines.push(line?);
+        }
+        Buffer::new(filepath, lines, languages)
+    }
+
+    pub(super) fn new(
+        filepath: String,
+        lines: Vec<String>,
+        languages: &HashMap<String, LanguageConfig>,
+    ) -> io::Result<Self> {
+        Ok(Buffer {
+            uri: language::path_to_uri(&filepath)?,
+            language: language::detect(&filepath, &lines, languages),
+            filepath,
+            lines,
+            cursor: (0, 0),
+            preserved_column: 0,
+            looking: (0, 0),
+            mark: None,
+            version: 1,
+            synced: true,
+            diagnostics: HashMap::new(),
+            inlay_hints: (0, Vec::new()),
+            inlay_hints_asked: None,
+            highlights: HashMap::new(),
+            semantic_tokens: None,
+            semantic_tokens_asked: None,
+            occurrences: HashMap::new(),
+            occurrences_asked: None,
+            folding_ranges: (0, Vec::new()),
+            folding_ranges_asked: None,
+            folded: Vec::new(),
+        })
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for line in &self.lines {
+            text += line;
+            text += "\n";
+        }
+        text
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(&self.filepath)?);
+        for line in &self.lines {
+            writer.write_all(line.as_bytes())?;
+            writer.write_all("\n".as_bytes())?;
+        }
+        writer.flush()
+    }
+
+    fn line(&self, line: usize) -> &str {
+        self.lines.get(line).map_or("", |line| line.as_str())
+    }
+
+    // Whether the character under or just before the cursor is part of a name
+    pub fn on_identifier(&self) -> bool {
+        let line = self.line(self.cursor.0);
+        let column = char_boundary(line, self.cursor.1);
+        let is_identifier = |ch: char| ch.is_alphanumeric() || ch == '_';
+        line[column..].chars().next().is_some_and(is_identifier)
+            || line[..column]
+                .chars()
+                .next_back()
+                .is_some_and(is_identifier)
+    }
+
+    // LSP counts columns in UTF-16 code units, the buffer in bytes
+    pub fn position_at(&self, (line, column): (usize, usize)) -> Position {
+        let line_str = self.line(line);
+        let column = char_boundary(line_str, column);
+        Position {
+            line: line as u32,
+            character: line_str[..column].encode_utf16().count() as u32,
+        }
+    }
+
+    pub fn cursor_at(&self, position: Position) -> (usize, usize) {
+        let line = cmp::min(position.line as usize, self.lines.len().saturating_sub(1));
+        let mut character = 0;
+        for (column, ch) in self.line(line).char_indices() {
+            if character >= position.character as usize {
+                return (line, column);
+            }
+            character += ch.len_utf16();
+        }
+        (line, self.line(line).len())
+    }
+
+    // Byte offset in text()
+    fn offset_at(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        if line >= self.lines.len() {
+            return self.lines.iter().map(|line| line.len() + 1).sum();
+        }
+        let start: usize = self.lines[..line].iter().map(|line| line.len() + 1).sum();
+        start + self.cursor_at(position).1
+    }
+
+    pub fn text_in(&self, range: Range) -> String {
+        let text = self.text();
+        let start = cmp::min(self.offset_at(range.start), text.len());
+        let end = cmp::max(cmp::min(self.offset_at(range.end), text.len()), start);
+        text[start..end].to_string()
+    }
+
+    // Identifier around the cursor
+    pub fn word(&self) -> String {
+        let line = self.line(self.cursor.0);
+        let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+        let column = char_boundary(line, self.cursor.1);
+        let start = line[..column]
+            .char_indices()
+            .rev()
+            .take_while(|(_, ch)| is_word(*ch))
+            .last()
+            .map_or(column, |(index, _)| index);
+        let end = line[column..]
+            .char_indices()
+            .find(|(_, ch)| !is_word(*ch))
+            .map_or(line.len(), |(index, _)| column + index);
+        line[start..end].to_string()
+    }
+
+    // Applies non-overlapping edits, keeping the cursor on the same text
+    pub fn apply_edits(&mut self, mut edits: Vec<TextEdit>) {
+        if edits.is_empty() {
+            return;
+        }
+        edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+        let mut text = self.text();
+        let mut cursor = self.offset_at(self.position());
+        for edit in edits.iter().rev() {
+            let start = cmp::min(self.offset_at(edit.range.start), text.len());
+            let end = cmp::max(cmp::min(self.offset_at(edit.range.end), text.len()), start);
+            text.replace_range(start..end, &edit.new_text);
+            if end <= cursor {
+                cursor = cursor + edit.new_text.len() - (end - start);
+            } else if start < cursor {
+                cursor = start;
+            }
+        }
+
+        let (before, _) = text.split_at(cmp::min(cursor, text.len()));
+        let line = before.matches('\n').count();
+        let column = before.len() - before.rfind('\n').map_or(0, |index| index + 1);
+        self.lines = text.lines().map(String::from).collect();
+        // The lines may have moved anywhere
+        self.folded.clear();
+        self.set_cursor((line, column));
+        self.modified();
+    }
+
+    pub fn position(&self) -> Position {
+        self.position_at(self.cursor)
+    }
+
+    // Between the mark and the cursor, or just the cursor without the mark
+    pub fn selection(&self) -> Range {
+        let mark = self.mark.unwrap_or(self.cursor);
+        Range {
+            start: self.position_at(cmp::min(mark, self.cursor)),
+            end: self.position_at(cmp::max(mark, self.cursor)),
+        }
+    }
+
+    pub fn toggle_mark(&mut self) {
+        self.mark = match self.mark {
+            Some(_) => None,
+            None => Some(self.cursor),
+        };
+    }
+
+    // Diagnostics on the lines of the range
+    // Those of the server, which are the only ones it knows about
+    pub fn diagnostics_in(&self, server: ServerId, range: Range) -> Vec<Diagnostic> {
+        self.diagnostics
+            .get(&server)
+            .into_iter()
+            .flatten()
+            .filter(|diagnostic| {
+                diagnostic.range.start.line <= range.end.line
+                    && range.start.line <= diagnostic.range.end.line
+            })
+            .cloned()
+            .collect()
+    }
+
+    // Virtual text by line as (column, text), unless the hints are of older content
+    pub fn inlay_hint_texts(&self) -> HashMap<usize, Vec<(usize, String)>> {
+        let mut texts: HashMap<usize, Vec<(usize, String)>> = HashMap::new();
+        let (version, hints) = &self.inlay_hints;
+        if *version != self.version {
+            return texts;
+        }
+        for hint in hints {
+            let (line, column) = self.cursor_at(hint.position);
+            texts.entry(line).or_default().push((column, hint.text()));
+        }
+        for line in texts.values_mut() {
+            line.sort_by_key(|(column, _)| *column);
+        }
+        texts
+    }
+
+    // Keeps the cursor in the window of the rows, counting only the visible lines
+    pub fn scroll(&mut self, rows: usize) {
+        self.looking.0 = self.visible_line(self.looking.0);
+        if self.cursor.0 < self.looking.0 {
+            self.looking.0 = self.cursor.0;
+        }
+        let mut shown = (self.looking.0..=self.cursor.0)
+            .filter(|line| !self.hidden(*line))
+            .count();
+        while shown > rows {
+            if !self.hidden(self.looking.0) {
+                shown -= 1;
+            }
+            self.looking.0 += 1;
+        }
+    }
+
+    // Opening the folds that hide the cursor
+    pub fn set_cursor(&mut self, cursor: (usize, usize)) {
+        self.folded
+            .retain(|(start, end)| !(*start < cursor.0 && cursor.0 <= *end));
+        self.cursor = cursor;
+        self.preserved_column = cursor.1;
+    }
+
+    fn modified(&mut self) {
+        self.version += 1;
+        self.synced = false;
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        if self.lines.len() <= self.cursor.0 {
+            self.lines.resize(self.cursor.0 + 1, Default::default());
+        }
+        let line = &mut self.lines[self.cursor.0];
+        let column = char_boundary(line, self.cursor.1);
+        line.insert(column, ch);
+        self.cursor.1 = column + ch.len_utf8();
+        self.modified();
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor.1 == 0 {
+            if self.cursor.0 == 0 {
+                return;
+            }
+            let prev_line_len = self.lines[self.cursor.0 - 1].len();
+            self.lines[self.cursor.0 - 1] = format!(
+                "{}{}",
+                self.lines[self.cursor.0 - 1],
+                self.lines[self.cursor.0]
+            );
+            for i in self.cursor.0..(self.lines.len() - 1) {
+                self.lines[i] = self.lines[i + 1].clone();
+            }
+            self.lines.pop();
+            self.shift_folds(self.cursor.0 - 1, -1);
+            self.cursor.0 -= 1;
+            self.cursor.1 = prev_line_len;
+            self.modified();
+            return;
+        }
+        let line = &mut self.lines[self.cursor.0];
+        let column = char_boundary(line, self.cursor.1);
+        if let Some((previous, _)) = line[..column].char_indices().next_back() {
+            line.remove(previous);
+            self.cursor.1 = previous;
+            self.modified();
+        }
+    }
+
+    pub fn nextline(&mut self) {
+        if self.lines.len() <= self.cursor.0 {
+            self.lines.resize(self.cursor.0 + 1, Default::default());
+        }
+        self.lines.push(String::new());
+        for i in ((self.cursor.0 + 1)..self.lines.len()).rev() {
+            self.lines[i] = self.lines[i - 1].clone();
+        }
+        let column = char_boundary(&self.lines[self.cursor.0], self.cursor.1);
+        self.lines[self.cursor.0 + 1] = self.lines[self.cursor.0].split_off(column);
+        self.shift_folds(self.cursor.0, 1);
+        self.cursor.0 += 1;
+        self.cursor.1 = 0;
+        self.modified();
+    }
+
+    // Over the folded lines
+    pub fn cursor_up(&mut self) {
+        if self.cursor.0 > 0 {
+            self.cursor.0 = self.visible_line(self.cursor.0 - 1);
+        }
+        self.cursor.1 = char_boundary(self.line(self.cursor.0), self.preserved_column);
+    }
+
+    pub fn cursor_down(&mut self) {
+        let mut line = self.cursor.0 + 1;
+        while line < self.lines.len() && self.hidden(line) {
+            line += 1;
+        }
+        if line < self.lines.len() {
+            self.cursor.0 = line;
+        }
+        self.cursor.1 = char_boundary(self.line(self.cursor.0), self.preserved_column);
+    }
+
+    // By a character, which may be several bytes
+    pub fn cursor_forward(&mut self) {
+        let line = self.line(self.cursor.0);
+        let column = char_boundary(line, self.cursor.1);
+        if let Some(ch) = line[column..].chars().next() {
+            self.cursor.1 = column + ch.len_utf8();
+            self.preserved_column = self.cursor.1;
+        }
+    }
+
+    pub fn cursor_back(&mut self) {
+        let line = self.line(self.cursor.0);
+        let column = char_boundary(line, self.cursor.1);
+        if let Some((previous, _)) = line[..column].char_indices().next_back() {
+            self.cursor.1 = previous;
+            self.preserved_column = self.cursor.1;
+        }
+    }
+
+    pub fn page_forward(&mut self) {
+        let window_size = terminal::size();
+        self.cursor.0 = cmp::min(
+            self.cursor.0 + window_size.0,
+            self.lines.len().saturating_sub(1),
+        );
+        self.looking.0 = cmp::min(
+            self.looking.0 + window_size.0,
+            self.lines.len().saturating_sub(window_size.0),
+        );
+        self.cursor.0 = self.visible_line(self.cursor.0);
+    }
+
+    pub fn page_back(&mut self) {
+        let window_size = terminal::size();
+        if self.cursor.0 > window_size.0 {
+            self.cursor.0 -= window_size.0;
+            if self.looking.0 > window_size.0 {
+                self.looking.0 -= window_size.0;
+            } else {
+                self.looking.0 = 0;
+            }
+        } else {
+            self.cursor.0 = 0;
+            self.looking.0 = 0;
+        }
+        self.cursor.0 = self.visible_line(self.cursor.0);
+    }
+}
+
+// The column moved back to the start of the character it falls in, within the line
+fn char_boundary(line: &str, column: usize) -> usize {
+    let mut column = cmp::min(column, line.len());
+    while !line.is_char_boundary(column) {
+        column -= 1;
+    }
+    column
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(lines: &[&str]) -> Buffer {
+        let lines = lines.iter().map(|line| line.to_string()).collect();
+        Buffer::new("test.txt".to_string(), lines, &HashMap::new()).unwrap()
+    }
+
+    fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range {
+                start: Position {
+                    line: start.0,
+                    character: start.1,
+                },
+                end: Position {
+                    line: end.0,
+                    character: end.1,
+                },
+            },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn applies_multiple_edits_in_any_order() {
+        let mut buffer = buffer(&["let a = 1;", "let b = a;"]);
+        buffer.apply_edits(vec![
+            edit((1, 8), (1, 9), "alpha"),
+            edit((0, 4), (0, 5), "alpha"),
+            edit((1, 4), (1, 5), "beta"),
+        ]);
+        assert_eq!(buffer.lines, ["let alpha = 1;", "let beta = alpha;"]);
+        assert_eq!(buffer.version, 2);
+    }
+
+    #[test]
+    fn keeps_the_cursor_after_an_insertion_at_it() {
+        let mut buffer = buffer(&["let a = 1;"]);
+        buffer.cursor = (0, 4);
+        buffer.apply_edits(vec![edit((0, 4), (0, 4), "mut ")]);
+        assert_eq!(buffer.lines, ["let mut a = 1;"]);
+        assert_eq!(buffer.cursor, (0, 8));
+    }
+
+    #[test]
+    fn moves_the_cursor_to_the_start_of_an_edit_around_it() {
+        let mut buffer = buffer(&["fn f() {", "    call(bar, baz);", "}"]);
+        buffer.cursor = (1, 10);
+        buffer.apply_edits(vec![
+            edit((1, 9), (1, 12), "x"),
+            edit((0, 0), (0, 0), "// f\n"),
+        ]);
+        assert_eq!(buffer.lines, ["// f", "fn f() {", "    call(x, baz);", "}"]);
+        assert_eq!(buffer.cursor, (2, 9));
+    }
+
+    #[test]
+    fn counts_positions_in_utf16() {
+        let mut buffer = buffer(&["h\u{e9}llo \u{1f600} w\u{f6}rld"]);
+        buffer.cursor = (0, buffer.lines[0].len());
+        // The emoji is two UTF-16 code units, four bytes
+        buffer.apply_edits(vec![edit((0, 9), (0, 14), "world")]);
+        assert_eq!(buffer.lines, ["h\u{e9}llo \u{1f600} world"]);
+        assert_eq!(buffer.cursor, (0, buffer.lines[0].len()));
+    }
+
+    #[test]
+    fn appends_edits_past_the_last_line() {
+        let mut buffer = buffer(&["a"]);
+        buffer.apply_edits(vec![edit((5, 0), (7, 3), "b\n")]);
+        assert_eq!(buffer.lines, ["a", "b"]);
+        assert_eq!(buffer.cursor, (0, 0));
+    }
+}
@@ -0,0 +1,236 @@
+use serde_json::Value;
+
+use super::{Buffer, Editor, Pending};
+use crate::language::{FoldingRange, Registry};
+
+impl Buffer {
+    // Those of the server for the current content, or else from the indentation
+    fn fold_ranges(&self) -> Vec<(usize, usize)> {
+        match &self.folding_ranges {
+            (version, ranges) if *version == self.version => ranges.clone(),
+            _ => indentation_ranges(&self.lines),
+        }
+    }
+
+    // Under a closed fold, below its first line
+    pub fn hidden(&self, line: usize) -> bool {
+        self.folded
+            .iter()
+            .any(|(start, end)| *start < line && line <= *end)
+    }
+
+    // The closed fold starting on the line, if any
+    pub fn folded_at(&self, line: usize) -> Option<(usize, usize)> {
+        self.folded
+            .iter()
+            .filter(|(start, _)| *start == line)
+            .max_by_key(|(_, end)| *end)
+            .copied()
+    }
+
+    // The rows of the window from the top, skipping the hidden lines
+    pub fn visible_lines(&self, rows: usize) -> Vec<usize> {
+        (self.looking.0..self.lines.len())
+            .filter(|line| !self.hidden(*line))
+            .take(rows)
+            .collect()
+    }
+
+    // The first line of the outermost closed fold hiding the line
+    pub fn visible_line(&self, line: usize) -> usize {
+        self.folded
+            .iter()
+            .filter(|(start, end)| *start < line && line <= *end)
+            .map(|(start, _)| *start)
+            .min()
+            .unwrap_or(line)
+    }
+
+    // The innermost range around the cursor line
+    pub fn fold(&mut self) {
+        let line = self.cursor.0;
+        let range = self
+            .fold_ranges()
+            .into_iter()
+            .filter(|(start, end)| {
+                *start <= line && line <= *end && !self.folded.contains(&(*start, *end))
+            })
+            .min_by_key(|(start, end)| end - start);
+        if let Some(range) = range {
+            self.folded.push(range);
+            self.cursor = (range.0, 0);
+            self.preserved_column = 0;
+        }
+    }
+
+    // Opens the innermost closed fold on the cursor line
+    pub fn unfold(&mut self) {
+        let line = self.cursor.0;
+        if let Some(index) = self
+            .folded
+            .iter()
+            .enumerate()
+            .filter(|(_, (start, end))| *start <= line && line <= *end)
+            .min_by_key(|(_, (start, end))| end - start)
+            .map(|(index, _)| index)
+        {
+            self.folded.remove(index);
+        }
+    }
+
+    pub fn toggle_fold(&mut self) {
+        if self.folded_at(self.cursor.0).is_some() {
+            self.unfold();
+        } else {
+            self.fold();
+        }
+    }
+
+    pub fn fold_all(&mut self) {
+        self.folded = self.fold_ranges();
+        self.cursor = (self.visible_line(self.cursor.0), 0);
+        self.preserved_column = 0;
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folded.clear();
+    }
+
+    // Lines were inserted (or removed with a negative count) after the line,
+    // opening the folds the change touches
+    pub fn shift_folds(&mut self, line: usize, count: isize) {
+        self.folded
+            .retain(|(start, end)| !(*start <= line && line <= *end));
+        for (start, end) in &mut self.folded {
+            if *start > line {
+                *start = start.saturating_add_signed(count);
+                *end = end.saturating_add_signed(count);
+            }
+        }
+    }
+}
+
+// A line starts a range when the next lines are indented deeper, up to the last of them
+fn indentation_ranges(lines: &[String]) -> Vec<(usize, usize)> {
+    let indent =
+        |line: &str| (!line.trim().is_empty()).then(|| line.len() - line.trim_start().len());
+    let mut ranges = Vec::new();
+    for (start, line) in lines.iter().enumerate() {
+        let Some(level) = indent(line) else {
+            continue;
+        };
+        let mut end = start;
+        for (index, line) in lines.iter().enumerate().skip(start + 1) {
+            match indent(line) {
+                Some(deeper) if deeper > level => end = index,
+                Some(_) => break,
+                None => (), // Blank lines belong to the range only when deeper ones follow
+            }
+        }
+        if end > start {
+            ranges.push((start, end));
+        }
+    }
+    ranges
+}
+
+impl Editor {
+    // Asked again whenever the content changes
+    pub(super) async fn update_folding_ranges(&mut self, servers: &mut Registry) {
+        let buffer = &self.buffers[self.current];
+        if buffer.folding_ranges_asked == Some(buffer.version) {
+            return;
+        }
+        let language = buffer.language.clone();
+        if servers
+            .find(language.as_deref(), "foldingRangeProvider")
+            .is_none()
+        {
+            return;
+        }
+
+        self.sync(servers).await;
+        let Some(client) = servers.find(language.as_deref(), "foldingRangeProvider") else {
+            return;
+        };
+        let buffer = &mut self.buffers[self.current];
+        buffer.folding_ranges_asked = Some(buffer.version);
+        let pending = Pending::FoldingRange(self.current, buffer.version);
+        let id = client.folding_range(&buffer.uri).await;
+        self.wait(client.id, id, pending);
+    }
+
+    pub(super) fn folding_ranges(&mut self, index: usize, version: i32, result: Value) {
+        let buffer = &mut self.buffers[index];
+        if buffer.version != version {
+            return;
+        }
+        let Ok(Some(ranges)) = serde_json::from_value::<Option<Vec<FoldingRange>>>(result) else {
+            return;
+        };
+        let ranges = ranges
+            .iter()
+            .map(|range| (range.start_line as usize, range.end_line as usize))
+            .filter(|(start, end)| start < end)
+            .collect();
+        buffer.folding_ranges = (version, ranges);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn buffer(count: usize, folded: &[(usize, usize)]) -> Buffer {
+        let lines = (0..count).map(|line| line.to_string()).collect();
+        let mut buffer = Buffer::new("test.txt".to_string(), lines, &HashMap::new()).unwrap();
+        buffer.folded = folded.to_vec();
+        buffer
+    }
+
+    #[test]
+    fn ranges_follow_the_indentation() {
+        let lines = lines(&[
+            "fn main() {",
+            "    if x {",
+            "        y();",
+            "",
+            "        z();",
+            "    }",
+            "",
+            "}",
+            "fn f() {}",
+        ]);
+        assert_eq!(indentation_ranges(&lines), [(0, 5), (1, 4)]);
+    }
+
+    #[test]
+    fn ranges_leave_out_trailing_blank_lines() {
+        let lines = lines(&["a:", "  b", "", "", "c"]);
+        assert_eq!(indentation_ranges(&lines), [(0, 1)]);
+        assert!(indentation_ranges(&[]).is_empty());
+    }
+
+    #[test]
+    fn shows_the_first_line_of_the_outermost_fold() {
+        let buffer = buffer(10, &[(1, 8), (3, 5)]);
+        assert_eq!(buffer.visible_line(4), 1);
+        assert_eq!(buffer.visible_line(1), 1);
+        assert_eq!(buffer.visible_line(9), 9);
+        assert_eq!(buffer.visible_lines(3), [0, 1, 9]);
+    }
+
+    #[test]
+    fn shifts_the_folds_below_and_opens_the_touched_ones() {
+        let mut buffer = buffer(20, &[(0, 2), (4, 6), (8, 10)]);
+        buffer.shift_folds(5, 2);
+        assert_eq!(buffer.folded, [(0, 2), (10, 12)]);
+        buffer.shift_folds(3, -1);
+        assert_eq!(buffer.folded, [(0, 2), (9, 11)]);
+    }
+}
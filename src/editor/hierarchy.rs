@@ -0,0 +1,272 @@
+use serde_json::Value;
+
+use super::symbol::kind_name;
+use super::{Editor, List, ListAction, Pending};
+use crate::language::{HierarchyItem, Location, Registry, ServerId};
+use crate::message::Id;
+
+// What the children of a node are
+#[derive(Clone, Copy)]
+pub enum Hierarchy {
+    Callers,
+    Callees,
+    Supertypes,
+    Subtypes,
+}
+
+impl Hierarchy {
+    fn capability(self) -> &'static str {
+        match self {
+            Hierarchy::Callers | Hierarchy::Callees => "callHierarchyProvider",
+            Hierarchy::Supertypes | Hierarchy::Subtypes => "typeHierarchyProvider",
+        }
+    }
+
+    fn prepare_method(self) -> &'static str {
+        match self {
+            Hierarchy::Callers | Hierarchy::Callees => "textDocument/prepareCallHierarchy",
+            Hierarchy::Supertypes | Hierarchy::Subtypes => "textDocument/prepareTypeHierarchy",
+        }
+    }
+
+    fn method(self) -> &'static str {
+        match self {
+            Hierarchy::Callers => "callHierarchy/incomingCalls",
+            Hierarchy::Callees => "callHierarchy/outgoingCalls",
+            Hierarchy::Supertypes => "typeHierarchy/supertypes",
+            Hierarchy::Subtypes => "typeHierarchy/subtypes",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Hierarchy::Callers => "Callers",
+            Hierarchy::Callees => "Callees",
+            Hierarchy::Supertypes => "Supertypes",
+            Hierarchy::Subtypes => "Subtypes",
+        }
+    }
+
+    // Calls wrap the item with the ranges of the calls, types are the items themselves
+    fn items(self, result: Value) -> Vec<Value> {
+        let results = hierarchy_items(result);
+        match self {
+            Hierarchy::Callers => results
+                .into_iter()
+                .map(|call| call["from"].clone())
+                .collect(),
+            Hierarchy::Callees => results.into_iter().map(|call| call["to"].clone()).collect(),
+            Hierarchy::Supertypes | Hierarchy::Subtypes => results,
+        }
+    }
+}
+
+fn hierarchy_items(result: Value) -> Vec<Value> {
+    match result {
+        Value::Array(items) => items,
+        _ => Vec::new(),
+    }
+}
+
+// The items in the order they are drawn, each under its parent
+pub struct Tree {
+    server: ServerId,
+    hierarchy: Hierarchy,
+    nodes: Vec<Node>,
+    visible: Vec<usize>, // Nodes under expanded parents, one per row of the list
+}
+
+struct Node {
+    item: Value, // As the server sent it
+    label: String,
+    location: Location,
+    depth: usize,
+    expanded: bool,
+    loaded: bool,      // Whether the children were asked
+    asked: Option<Id>, // The request for the children, until answered
+}
+
+impl Tree {
+    fn new(server: ServerId, hierarchy: Hierarchy, items: Vec<Value>) -> Self {
+        let mut tree = Tree {
+            server,
+            hierarchy,
+            nodes: Vec::new(),
+            visible: Vec::new(),
+        };
+        tree.insert(0, 0, items);
+        tree
+    }
+
+    // The items that parse, at the position in the nodes
+    fn insert(&mut self, at: usize, depth: usize, items: Vec<Value>) {
+        let nodes = items.into_iter().filter_map(|item| {
+            let parsed = serde_json::from_value::<HierarchyItem>(item.clone()).ok()?;
+            let mut label = format!("{} {}", parsed.name, kind_name(parsed.kind));
+            if let Some(detail) = &parsed.detail {
+                label += &format!("  {}", detail);
+            }
+            Some(Node {
+                item,
+                label,
+                location: Location {
+                    uri: parsed.uri,
+                    range: parsed.selection_range,
+                },
+                depth,
+                expanded: false,
+                loaded: false,
+                asked: None,
+            })
+        });
+        self.nodes.splice(at..at, nodes.collect::<Vec<_>>());
+    }
+
+    // Hides the descendants of collapsed nodes; "+" can be expanded, "-" can be collapsed
+    fn labels(&mut self) -> Vec<String> {
+        self.visible.clear();
+        let mut labels = Vec::new();
+        let mut hidden_below = None;
+        for (index, node) in self.nodes.iter().enumerate() {
+            if hidden_below.is_some_and(|depth| node.depth > depth) {
+                continue;
+            }
+            hidden_below = (!node.expanded).then_some(node.depth);
+            let has_children = self
+                .nodes
+                .get(index + 1)
+                .is_some_and(|next| next.depth > node.depth);
+            let marker = match (node.expanded, node.loaded) {
+                (true, _) if has_children => "-",
+                (false, false) => "+",
+                (false, true) if has_children => "+",
+                _ => " ",
+            };
+            labels.push(format!(
+                "{}{} {}",
+                "  ".repeat(node.depth),
+                marker,
+                node.label
+            ));
+            self.visible.push(index);
+        }
+        labels
+    }
+
+    fn node(&self, row: usize) -> Option<usize> {
+        self.visible.get(row).copied()
+    }
+
+    pub fn location(&self, row: usize) -> Option<&Location> {
+        Some(&self.nodes[self.node(row)?].location)
+    }
+}
+
+impl Editor {
+    pub(super) async fn hierarchy(&mut self, servers: &mut Registry, hierarchy: Hierarchy) {
+        self.sync(servers).await;
+        let Some(client) = self.server(servers, hierarchy.capability()) else {
+            return;
+        };
+        let buffer = self.buffer();
+        let position = buffer.position_at(buffer.cursor);
+        let id = client
+            .prepare_hierarchy(hierarchy.prepare_method(), &buffer.uri, position)
+            .await;
+        self.wait(client.id, id, Pending::PrepareHierarchy(hierarchy));
+    }
+
+    // The symbols under the cursor as the roots, expanded right away
+    pub(super) async fn show_hierarchy(
+        &mut self,
+        servers: &mut Registry,
+        server: ServerId,
+        hierarchy: Hierarchy,
+        result: Value,
+    ) {
+        let tree = Tree::new(server, hierarchy, hierarchy_items(result));
+        if tree.nodes.is_empty() {
+            self.message = Some("NO SYMBOL UNDER THE CURSOR".to_string());
+            return;
+        }
+        let roots = tree.nodes.len();
+        self.show_list(
+            List::new(String::new(), Vec::new()),
+            ListAction::Hierarchy(tree),
+        );
+        self.redraw_tree();
+        for row in 0..roots {
+            self.expand(servers, row).await;
+        }
+    }
+
+    // Asks the children the first time, shows them again after that
+    pub(super) async fn expand(&mut self, servers: &mut Registry, row: usize) {
+        let Some((_, ListAction::Hierarchy(tree))) = &mut self.list else {
+            return;
+        };
+        let Some(index) = tree.node(row) else {
+            return;
+        };
+        let node = &mut tree.nodes[index];
+        node.expanded = true;
+        if node.loaded {
+            self.redraw_tree();
+            return;
+        }
+        node.loaded = true;
+        let (server, hierarchy, item) = (tree.server, tree.hierarchy, node.item.clone());
+        let Some(client) = servers.get(server) else {
+            return;
+        };
+        let id = client.hierarchy(hierarchy.method(), &item).await;
+        if let (Ok(id), Some((_, ListAction::Hierarchy(tree)))) = (&id, &mut self.list) {
+            tree.nodes[index].asked = Some(id.clone());
+        }
+        self.wait(server, id, Pending::Hierarchy);
+    }
+
+    pub(super) fn collapse(&mut self, row: usize) {
+        let Some((_, ListAction::Hierarchy(tree))) = &mut self.list else {
+            return;
+        };
+        if let Some(index) = tree.node(row) {
+            tree.nodes[index].expanded = false;
+            self.redraw_tree();
+        }
+    }
+
+    // Under the node that asked, if the tree is still open
+    pub(super) fn hierarchy_children(&mut self, server: ServerId, id: Id, result: Value) {
+        let Some((_, ListAction::Hierarchy(tree))) = &mut self.list else {
+            return;
+        };
+        if tree.server != server {
+            return;
+        }
+        let Some(index) = tree
+            .nodes
+            .iter()
+            .position(|node| node.asked.as_ref() == Some(&id))
+        else {
+            return;
+        };
+        tree.nodes[index].asked = None;
+        let depth = tree.nodes[index].depth + 1;
+        let items = tree.hierarchy.items(result);
+        tree.insert(index + 1, depth, items);
+        self.redraw_tree();
+    }
+
+    fn redraw_tree(&mut self) {
+        let Some((list, ListAction::Hierarchy(tree))) = &mut self.list else {
+            return;
+        };
+        let labels = tree.labels();
+        let title = format!(
+            "{} (l: expand, h: collapse, Enter: jump)",
+            tree.hierarchy.title()
+        );
+        list.set(title, labels);
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use super::{Buffer, Editor, Pending};
+use crate::language::{DocumentHighlight, Registry};
+
+// Background colors of the occurrences
+const READ: u8 = 90; // Gray
+const WRITE: u8 = 34; // Blue
+
+impl Editor {
+    // Asked whenever the cursor lands on an identifier, kept while it stays in an occurrence
+    pub(super) async fn update_document_highlights(&mut self, servers: &mut Registry) {
+        let buffer = &mut self.buffers[self.current];
+        let (line, column) = buffer.cursor;
+        let inside = buffer.occurrences.get(&line).is_some_and(|occurrences| {
+            occurrences
+                .iter()
+                .any(|(start, end, _)| *start <= column && column <= *end)
+        });
+        if !inside
+            || buffer
+                .occurrences_asked
+                .is_some_and(|asked| asked.0 != buffer.version)
+        {
+            buffer.occurrences.clear();
+        }
+        let asked = Some((buffer.version, buffer.cursor));
+        if buffer.occurrences_asked == asked {
+            return;
+        }
+        let language = buffer.language.clone();
+        if servers
+            .find(language.as_deref(), "documentHighlightProvider")
+            .is_none()
+            || !self.buffers[self.current].on_identifier()
+        {
+            return;
+        }
+
+        self.sync(servers).await;
+        let Some(client) = servers.find(language.as_deref(), "documentHighlightProvider") else {
+            return;
+        };
+        let buffer = &mut self.buffers[self.current];
+        buffer.occurrences_asked = asked;
+        let pending = Pending::DocumentHighlight(self.current, buffer.version, buffer.cursor);
+        let position = buffer.position_at(buffer.cursor);
+        let id = client.document_highlight(&buffer.uri, position).await;
+        self.wait(client.id, id, pending);
+    }
+
+    // Only while the cursor is still where it was asked
+    pub(super) fn document_highlights(
+        &mut self,
+        index: usize,
+        asked: (i32, (usize, usize)),
+        result: serde_json::Value,
+    ) {
+        let buffer = &mut self.buffers[index];
+        if (buffer.version, buffer.cursor) != asked {
+            return;
+        }
+        let Ok(Some(highlights)) = serde_json::from_value::<Option<Vec<DocumentHighlight>>>(result)
+        else {
+            return;
+        };
+        buffer.occurrences = occurrences(buffer, &highlights);
+    }
+}
+
+// Split into lines, as the ranges may span several
+fn occurrences(
+    buffer: &Buffer,
+    highlights: &[DocumentHighlight],
+) -> HashMap<usize, Vec<(usize, usize, u8)>> {
+    let mut occurrences: HashMap<usize, Vec<(usize, usize, u8)>> = HashMap::new();
+    for highlight in highlights {
+        let color = if highlight.kind == Some(3) {
+            WRITE
+        } else {
+            READ
+        };
+        let start = buffer.cursor_at(highlight.range.start);
+        let end = buffer.cursor_at(highlight.range.end);
+        for line in start.0..=end.0 {
+            let from = if line == start.0 { start.1 } else { 0 };
+            let to = if line == end.0 {
+                end.1
+            } else {
+                buffer.lines[line].len()
+            };
+            occurrences.entry(line).or_default().push((from, to, color));
+        }
+    }
+    occurrences
+}
@@ -0,0 +1,183 @@
+use super::{Editor, List, ListAction, Mode, Pending, PromptAction, Signature};
+use crate::language;
+use crate::language::{
+    CodeActionOrCommand, DocumentSymbolResult, GotoResult, InlayHint, Location,
+    PrepareRenameResult, Registry, ServerId, SignatureHelp, SymbolInformation, TextEdit,
+    WorkspaceEdit,
+};
+use crate::message::Id;
+
+impl Editor {
+    // Responses to the requests sent by Client::spawn_request
+    pub async fn response_handler(
+        &mut self,
+        server: ServerId,
+        id: Id,
+        result: Result<serde_json::Value, language::Error>,
+        servers: &mut Registry,
+    ) {
+        let Some(pending) = self.pending.remove(&(server, id.clone())) else {
+            return;
+        };
+        let result = match result {
+            Ok(result) => result,
+            // The newer request that superseded it is answered instead, but a save still happens
+            Err(language::Error::Cancelled(_))
+                if !matches!(pending, Pending::Formatting(_, _, true)) =>
+            {
+                return;
+            }
+            Err(err) => {
+                if !err.is_silent() {
+                    self.message = Some(err.to_string());
+                }
+                serde_json::Value::Null
+            }
+        };
+
+        match pending {
+            Pending::Initialize => {
+                // The error is shown already
+                if !result.is_null() {
+                    self.server_initialized(servers, server, result).await;
+                }
+            }
+            Pending::Goto(method) => {
+                let Ok(result) = serde_json::from_value::<GotoResult>(result) else {
+                    return;
+                };
+                let locations: Vec<Location> = result.into();
+                match locations.len() {
+                    0 => {}
+                    1 => self.jump(servers, &locations[0]).await,
+                    _ => self.show_locations(method, locations),
+                }
+            }
+            Pending::References => {
+                let Ok(Some(locations)) = serde_json::from_value::<Option<Vec<Location>>>(result)
+                else {
+                    return;
+                };
+                let title = format!("{} references", locations.len());
+                self.show_locations(title, locations);
+            }
+            Pending::PrepareRename(index, position) => {
+                let buffer = &self.buffers[index];
+                let placeholder = match serde_json::from_value(result) {
+                    Ok(Some(PrepareRenameResult::Range(range))) => buffer.text_in(range),
+                    Ok(Some(PrepareRenameResult::Placeholder { placeholder })) => placeholder,
+                    Ok(Some(PrepareRenameResult::DefaultBehavior {
+                        default_behavior: true,
+                    })) => buffer.word(),
+                    _ => return, // Nothing to rename here
+                };
+                self.prompt_rename(server, index, position, placeholder);
+            }
+            Pending::Rename(index, version) => {
+                // The edit is stale if the buffer was modified in the meantime
+                if self.buffers[index].version != version {
+                    return;
+                }
+                if let Ok(Some(edit)) = serde_json::from_value::<Option<WorkspaceEdit>>(result) {
+                    let result = self.apply_workspace_edit(servers, edit).await;
+                    self.report(result);
+                }
+            }
+            Pending::CodeAction(index, version) => {
+                if self.buffers[index].version != version {
+                    return;
+                }
+                let Ok(Some(actions)) =
+                    serde_json::from_value::<Option<Vec<CodeActionOrCommand>>>(result)
+                else {
+                    return;
+                };
+                let actions: Vec<CodeActionOrCommand> = actions
+                    .into_iter()
+                    .filter(|action| match action {
+                        CodeActionOrCommand::CodeAction(action) => action.disabled.is_none(),
+                        CodeActionOrCommand::Command(_) => true,
+                    })
+                    .collect();
+                if actions.is_empty() {
+                    return;
+                }
+                let labels = actions
+                    .iter()
+                    .map(|action| action.title().to_string())
+                    .collect();
+                let list = List::new("Code actions".to_string(), labels);
+                self.show_list(list, ListAction::CodeAction(server, actions));
+            }
+            Pending::Formatting(index, version, save) => {
+                let buffer = &mut self.buffers[index];
+                if buffer.version == version {
+                    if let Ok(Some(edits)) = serde_json::from_value::<Option<Vec<TextEdit>>>(result)
+                    {
+                        buffer.apply_edits(edits);
+                    }
+                }
+                if save {
+                    self.save(index);
+                }
+            }
+            Pending::ExecuteCommand => {}
+            Pending::InlayHint(index, version) => {
+                let buffer = &mut self.buffers[index];
+                if buffer.version != version {
+                    return;
+                }
+                let hints = serde_json::from_value::<Option<Vec<InlayHint>>>(result);
+                buffer.inlay_hints = (version, hints.ok().flatten().unwrap_or_default());
+            }
+            Pending::PrepareHierarchy(hierarchy) => {
+                self.show_hierarchy(servers, server, hierarchy, result)
+                    .await;
+            }
+            Pending::Hierarchy => self.hierarchy_children(server, id, result),
+            Pending::FoldingRange(index, version) => self.folding_ranges(index, version, result),
+            Pending::DocumentHighlight(index, version, cursor) => {
+                self.document_highlights(index, (version, cursor), result);
+            }
+            Pending::SemanticTokens(index, version) => {
+                self.semantic_tokens(server, index, version, result, servers)
+                    .await;
+            }
+            Pending::DocumentSymbol(index) => {
+                if let Ok(result) = serde_json::from_value::<DocumentSymbolResult>(result) {
+                    self.show_document_symbols(index, result);
+                }
+            }
+            Pending::WorkspaceSymbol(query) => {
+                // Stale unless the prompt still holds the query
+                if !matches!(&self.prompt, Some((prompt, PromptAction::WorkspaceSymbol(_))) if prompt.input == query)
+                {
+                    return;
+                }
+                let symbols = serde_json::from_value::<Option<Vec<SymbolInformation>>>(result);
+                self.show_workspace_symbols(&query, symbols.ok().flatten().unwrap_or_default());
+            }
+            Pending::SignatureHelp(index) => {
+                // Null when the cursor is not in a call anymore
+                self.signature = None;
+                if index == self.current && matches!(self.mode, Mode::Normal) {
+                    self.signature = serde_json::from_value::<Option<SignatureHelp>>(result)
+                        .ok()
+                        .flatten()
+                        .and_then(Signature::new);
+                }
+            }
+            Pending::RangeFormatting(index, version) => {
+                let buffer = &mut self.buffers[index];
+                if buffer.version != version {
+                    return;
+                }
+                if let Ok(Some(edits)) = serde_json::from_value::<Option<Vec<TextEdit>>>(result) {
+                    buffer.apply_edits(edits);
+                }
+            }
+        }
+
+        self.refresh();
+    }
+}
@@ -0,0 +1,100 @@
+use std::cmp;
+
+use crate::terminal;
+
+// Selectable list drawn over the whole window
+pub struct List {
+    title: String,
+    labels: Vec<String>,
+    pub selected: usize,
+    looking: usize,
+    footer: bool, // Whether the bottom row is left for a prompt
+}
+
+impl List {
+    pub fn new(title: String, labels: Vec<String>) -> Self {
+        List {
+            title,
+            labels,
+            selected: 0,
+            looking: 0,
+            footer: false,
+        }
+    }
+
+    pub fn above_prompt(mut self) -> Self {
+        self.footer = true;
+        self
+    }
+
+    pub fn cursor_up(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+        }
+    }
+
+    pub fn cursor_down(&mut self) {
+        if self.selected + 1 < self.labels.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn page_forward(&mut self) {
+        let height = self.height();
+        self.selected = cmp::min(self.selected + height, self.labels.len().saturating_sub(1));
+    }
+
+    pub fn page_back(&mut self) {
+        self.selected = self.selected.saturating_sub(self.height());
+    }
+
+    // New items in place, keeping the selected row
+    pub fn set(&mut self, title: String, labels: Vec<String>) {
+        self.title = title;
+        self.labels = labels;
+        self.selected = cmp::min(self.selected, self.labels.len().saturating_sub(1));
+    }
+
+    // Rows available for the items, below the title
+    fn height(&self) -> usize {
+        let footer = if self.footer { 1 } else { 0 };
+        cmp::max(terminal::size().0, 2 + footer) - 1 - footer
+    }
+
+    pub fn refresh(&mut self) {
+        let (rows, columns) = terminal::size();
+        let height = self.height();
+        if self.selected < self.looking {
+            self.looking = self.selected;
+        }
+        if self.selected >= self.looking + height {
+            self.looking = self.selected - height + 1;
+        }
+
+        terminal::move_cursor(1, 1);
+        terminal::clear_line();
+        terminal::reverse_video();
+        print!("{}", truncate(&self.title, columns));
+        terminal::reset_style();
+        for row in 1..rows {
+            terminal::move_cursor(row + 1, 1);
+            terminal::clear_line();
+            let index = self.looking + row - 1;
+            if let Some(label) = self.labels.get(index) {
+                if index == self.selected {
+                    terminal::reverse_video();
+                }
+                print!("{}", truncate(label, columns));
+                terminal::reset_style();
+            }
+        }
+        terminal::move_cursor(self.selected - self.looking + 2, 1);
+    }
+}
+
+pub fn truncate(text: &str, width: usize) -> &str {
+    match text.char_indices().nth(width) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
@@ -0,0 +1,33 @@
+use super::list::truncate;
+use crate::terminal;
+
+// Single line input on the bottom row
+pub struct Prompt {
+    label: String,
+    pub input: String,
+}
+
+impl Prompt {
+    pub fn new(label: String, input: String) -> Self {
+        Prompt { label, input }
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        self.input.push(ch);
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    pub fn refresh(&self) {
+        let (rows, columns) = terminal::size();
+        let line = format!("{}: {}", self.label, self.input);
+        terminal::move_cursor(rows, 1);
+        terminal::clear_line();
+        terminal::reverse_video();
+        print!("{}", truncate(&line, columns));
+        terminal::reset_style();
+        terminal::move_cursor(rows, line.chars().count() + 1);
+    }
+}
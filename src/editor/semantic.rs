@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use super::{Buffer, Editor, Pending};
+use crate::language::{Position, Registry, SemanticTokensEdit, SemanticTokensResult, ServerId};
+
+// Colors of the token types the theme of the config does not set
+const DEFAULT_THEME: &[(&str, &str)] = &[
+    ("namespace", "cyan"),
+    ("type", "yellow"),
+    ("class", "yellow"),
+    ("enum", "yellow"),
+    ("interface", "yellow"),
+    ("struct", "yellow"),
+    ("typeParameter", "yellow"),
+    ("enumMember", "cyan"),
+    ("function", "blue"),
+    ("method", "blue"),
+    ("macro", "cyan"),
+    ("keyword", "magenta"),
+    ("modifier", "magenta"),
+    ("comment", "gray"),
+    ("string", "green"),
+    ("number", "red"),
+    ("regexp", "green"),
+    ("decorator", "cyan"),
+];
+
+const COLORS: &[(&str, u8)] = &[
+    ("black", 30),
+    ("red", 31),
+    ("green", 32),
+    ("yellow", 33),
+    ("blue", 34),
+    ("magenta", 35),
+    ("cyan", 36),
+    ("white", 37),
+    ("gray", 90),
+    ("bright-red", 91),
+    ("bright-green", 92),
+    ("bright-yellow", 93),
+    ("bright-blue", 94),
+    ("bright-magenta", 95),
+    ("bright-cyan", 96),
+    ("bright-white", 97),
+];
+
+impl Editor {
+    // Asked again whenever the content changes, as a delta when the server keeps the last result
+    pub(super) async fn update_semantic_tokens(&mut self, servers: &mut Registry) {
+        let buffer = &self.buffers[self.current];
+        if buffer.semantic_tokens_asked == Some(buffer.version) {
+            return;
+        }
+        self.ask_semantic_tokens(servers, self.current).await;
+    }
+
+    async fn ask_semantic_tokens(&mut self, servers: &mut Registry, index: usize) {
+        let language = self.buffers[index].language.clone();
+        if servers
+            .find(language.as_deref(), "semanticTokensProvider")
+            .is_none()
+        {
+            return;
+        }
+
+        self.sync_buffer(servers, index).await;
+        let Some(client) = servers.find(language.as_deref(), "semanticTokensProvider") else {
+            return;
+        };
+        let delta = client.capabilities["semanticTokensProvider"]["full"]["delta"] == true;
+        let buffer = &mut self.buffers[index];
+        buffer.semantic_tokens_asked = Some(buffer.version);
+        let pending = Pending::SemanticTokens(index, buffer.version);
+        let id = match &buffer.semantic_tokens {
+            Some((server, result_id, _)) if delta && *server == client.id => {
+                client.semantic_tokens_delta(&buffer.uri, result_id).await
+            }
+            _ => client.semantic_tokens_full(&buffer.uri).await,
+        };
+        self.wait(client.id, id, pending);
+    }
+
+    // A delta that cannot be applied is followed right away by a request for all the tokens
+    pub(super) async fn semantic_tokens(
+        &mut self,
+        server: ServerId,
+        index: usize,
+        version: i32,
+        result: serde_json::Value,
+        servers: &mut Registry,
+    ) {
+        let Some(client) = servers.get(server) else {
+            return;
+        };
+        let colors: Vec<Option<u8>> = client
+            .semantic_token_types()
+            .iter()
+            .map(|token_type| self.theme_color(token_type))
+            .collect();
+        let buffer = &mut self.buffers[index];
+        // A newer request is on its way, based on the result the buffer still has
+        if buffer.version != version {
+            return;
+        }
+        let (result_id, data) = match serde_json::from_value::<SemanticTokensResult>(result) {
+            Ok(SemanticTokensResult::Full(tokens)) => (tokens.result_id, tokens.data),
+            Ok(SemanticTokensResult::Delta(delta)) => match buffer.semantic_tokens.take() {
+                Some((_, _, data)) => (delta.result_id, apply_edits(data, delta.edits)),
+                None => return self.ask_semantic_tokens(servers, index).await,
+            },
+            // Such as a result ID the server forgot
+            Ok(SemanticTokensResult::Null) | Err(_) if buffer.semantic_tokens.is_some() => {
+                buffer.semantic_tokens = None;
+                return self.ask_semantic_tokens(servers, index).await;
+            }
+            Ok(SemanticTokensResult::Null) | Err(_) => (None, Vec::new()),
+        };
+        buffer.highlights = decode(buffer, &data, &colors);
+        buffer.semantic_tokens = result_id.map(|result_id| (server, result_id, data));
+    }
+
+    fn theme_color(&self, token_type: &str) -> Option<u8> {
+        let name = match self.config.theme.get(token_type) {
+            Some(name) => name.as_str(),
+            None => DEFAULT_THEME
+                .iter()
+                .find(|(default, _)| *default == token_type)
+                .map(|(_, name)| *name)?,
+        };
+        COLORS
+            .iter()
+            .find(|(color, _)| *color == name)
+            .map(|(_, code)| *code)
+    }
+}
+
+// Each edit replaces a slice of the integers of the previous result
+fn apply_edits(mut data: Vec<u32>, mut edits: Vec<SemanticTokensEdit>) -> Vec<u32> {
+    edits.sort_by_key(|edit| edit.start);
+    for edit in edits.into_iter().rev() {
+        let start = edit.start.min(data.len());
+        let end = (edit.start + edit.delete_count).min(data.len());
+        data.splice(start..end, edit.data);
+    }
+    data
+}
+
+// Tokens are 5 integers: line and start relative to the previous token, length, type and modifiers
+fn decode(
+    buffer: &Buffer,
+    data: &[u32],
+    colors: &[Option<u8>],
+) -> HashMap<usize, Vec<(usize, usize, u8)>> {
+    let mut highlights: HashMap<usize, Vec<(usize, usize, u8)>> = HashMap::new();
+    let (mut line, mut start) = (0, 0);
+    for token in data.chunks_exact(5) {
+        if token[0] > 0 {
+            line += token[0];
+            start = token[1];
+        } else {
+            start += token[1];
+        }
+        let Some(color) = colors.get(token[3] as usize).copied().flatten() else {
+            continue;
+        };
+        let from = buffer.cursor_at(Position {
+            line,
+            character: start,
+        });
+        let to = buffer.cursor_at(Position {
+            line,
+            character: start + token[2],
+        });
+        if from.0 == line as usize {
+            highlights
+                .entry(from.0)
+                .or_default()
+                .push((from.1, to.1, color));
+        }
+    }
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(start: usize, delete_count: usize, data: &[u32]) -> SemanticTokensEdit {
+        SemanticTokensEdit {
+            start,
+            delete_count,
+            data: data.to_vec(),
+        }
+    }
+
+    fn buffer(lines: &[&str]) -> Buffer {
+        let lines = lines.iter().map(|line| line.to_string()).collect();
+        Buffer::new("test.txt".to_string(), lines, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn splices_edits_in_any_order() {
+        let data = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let edits = vec![edit(7, 1, &[70, 71]), edit(2, 3, &[]), edit(0, 0, &[99])];
+        assert_eq!(apply_edits(data, edits), [99, 0, 1, 5, 6, 70, 71, 8, 9]);
+    }
+
+    #[test]
+    fn clamps_edits_past_the_end() {
+        assert_eq!(apply_edits(vec![0, 1], vec![edit(1, 5, &[2])]), [0, 2]);
+        assert_eq!(apply_edits(vec![0, 1], vec![edit(9, 1, &[2])]), [0, 1, 2]);
+    }
+
+    #[test]
+    fn decodes_relative_lines_and_starts() {
+        let buffer = buffer(&["fn main() {", "    let x = 1;", "}"]);
+        let colors = [Some(1), Some(2), None];
+        #[rustfmt::skip]
+        let data = [
+            0, 0, 2, 0, 0, // "fn"
+            0, 3, 4, 1, 0, // "main", 3 after the previous start
+            1, 4, 3, 0, 0, // "let", on the next line from its start
+            0, 4, 1, 2, 0, // "x" has no color
+        ];
+        let highlights = decode(&buffer, &data, &colors);
+        assert_eq!(highlights[&0], [(0, 2, 1), (3, 7, 2)]);
+        assert_eq!(highlights[&1], [(4, 7, 1)]);
+        assert_eq!(highlights.len(), 2);
+    }
+
+    #[test]
+    fn decodes_utf16_starts_to_bytes() {
+        let buffer = buffer(&["\"\u{1f600}\" + s"]);
+        let highlights = decode(&buffer, &[0, 0, 4, 0, 0, 0, 7, 1, 0, 0], &[Some(3)]);
+        assert_eq!(highlights[&0], [(0, 6, 3), (9, 10, 3)]);
+    }
+
+    #[test]
+    fn drops_tokens_past_the_last_line() {
+        let buffer = buffer(&["a", "b"]);
+        let highlights = decode(&buffer, &[1, 0, 1, 0, 0, 1, 0, 1, 0, 0], &[Some(3)]);
+        assert_eq!(highlights[&1], [(0, 1, 3)]);
+        assert_eq!(highlights.len(), 1);
+    }
+}
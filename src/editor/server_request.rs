@@ -0,0 +1,102 @@
+use serde_json::json;
+
+use super::{Editor, List, ListAction};
+use crate::language::{Registry, ServerId, WorkspaceEdit};
+use crate::message::{Request, Response, ResponseError};
+
+impl Editor {
+    // Requests sent from the language server to the editor
+    pub async fn request_handler(
+        &mut self,
+        server: ServerId,
+        request: Request,
+        servers: &mut Registry,
+    ) {
+        let id = request.id.clone();
+        let response = match request.method.as_str() {
+            "workspace/configuration" => {
+                let Some(client) = servers.get(server) else {
+                    return;
+                };
+                let settings: Vec<_> = request.params["items"]
+                    .as_array()
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|item| client.settings(item["section"].as_str()))
+                    .collect();
+                Response::new(id, json!(settings))
+            }
+            "workspace/workspaceFolders" => {
+                match servers.get(server).map(|client| client.workspace_folders()) {
+                    Some(Ok(folders)) => Response::new(id, folders),
+                    Some(Err(err)) => {
+                        Response::error(id, ResponseError::INTERNAL_ERROR, &err.to_string())
+                    }
+                    None => return,
+                }
+            }
+            "workspace/applyEdit" => {
+                match serde_json::from_value::<WorkspaceEdit>(request.params["edit"].clone()) {
+                    Ok(edit) => match self.apply_workspace_edit(servers, edit).await {
+                        Ok(_) => Response::new(id, json!({ "applied": true })),
+                        Err(err) => Response::new(
+                            id,
+                            json!({ "applied": false, "failureReason": err.to_string() }),
+                        ),
+                    },
+                    Err(err) => {
+                        Response::error(id, ResponseError::INVALID_PARAMS, &err.to_string())
+                    }
+                }
+            }
+            // Asked again on the next keypress
+            "workspace/inlayHint/refresh" => {
+                for buffer in &mut self.buffers {
+                    buffer.inlay_hints_asked = None;
+                }
+                Response::new(id, serde_json::Value::Null)
+            }
+            "workspace/semanticTokens/refresh" => {
+                for buffer in &mut self.buffers {
+                    buffer.semantic_tokens_asked = None;
+                }
+                Response::new(id, serde_json::Value::Null)
+            }
+            "client/registerCapability"
+            | "client/unregisterCapability"
+            | "window/workDoneProgress/create" => Response::new(id, serde_json::Value::Null),
+            "window/showMessageRequest" => {
+                let actions = request.params["actions"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                if actions.is_empty() || self.list.is_some() {
+                    Response::new(id, serde_json::Value::Null)
+                } else {
+                    // Answered when one of the actions is selected
+                    let title = request.params["message"].as_str().unwrap_or_default();
+                    let labels = actions
+                        .iter()
+                        .map(|action| action["title"].as_str().unwrap_or_default().to_string())
+                        .collect();
+                    let list = List::new(title.to_string(), labels);
+                    self.list = Some((list, ListAction::MessageAction(server, id, actions)));
+                    self.refresh();
+                    return;
+                }
+            }
+            method => Response::error(
+                id,
+                ResponseError::METHOD_NOT_FOUND,
+                &format!("UNSUPPORTED METHOD {}", method),
+            ),
+        };
+        let Some(client) = servers.get(server) else {
+            return;
+        };
+        let result = client.respond(response).await;
+        self.report(result);
+        self.refresh();
+    }
+}
@@ -0,0 +1,85 @@
+use std::cmp;
+
+use super::list::truncate;
+use crate::language::{ParameterLabel, SignatureHelp};
+use crate::terminal;
+
+// The active signature in a box above the cursor, its current parameter underlined
+pub struct Signature {
+    label: String,
+    parameter: Option<(usize, usize)>, // Byte range in the label
+}
+
+impl Signature {
+    pub fn new(help: SignatureHelp) -> Option<Self> {
+        let index = help.active_signature.unwrap_or(0) as usize;
+        let signature = help
+            .signatures
+            .get(index)
+            .or(help.signatures.first())?
+            .clone();
+        let active = signature
+            .active_parameter
+            .or(help.active_parameter)
+            .unwrap_or(0) as usize;
+        let parameter =
+            signature
+                .parameters
+                .get(active)
+                .and_then(|parameter| match &parameter.label {
+                    ParameterLabel::Text(text) => signature
+                        .label
+                        .find(text.as_str())
+                        .map(|start| (start, start + text.len())),
+                    // Reversed offsets from a faulty server underline nothing
+                    ParameterLabel::Offsets([start, end]) => (start <= end).then(|| {
+                        (
+                            byte_offset(&signature.label, *start),
+                            byte_offset(&signature.label, *end),
+                        )
+                    }),
+                });
+        Some(Signature {
+            label: signature.label,
+            parameter,
+        })
+    }
+
+    // Below the cursor when it is on the top row
+    pub fn refresh(&self, (row, column): (usize, usize)) {
+        let (rows, columns) = terminal::size();
+        let label = truncate(&self.label, columns);
+        let width = label.chars().count();
+        let row = if row > 1 {
+            row - 1
+        } else {
+            cmp::min(row + 1, rows)
+        };
+        let column = cmp::min(column, columns.saturating_sub(width) + 1);
+        let (start, end) = self.parameter.map_or((0, 0), |(start, end)| {
+            (cmp::min(start, label.len()), cmp::min(end, label.len()))
+        });
+
+        terminal::move_cursor(row, column);
+        terminal::reverse_video();
+        print!("{}", &label[..start]);
+        terminal::underline();
+        print!("{}", &label[start..end]);
+        terminal::reset_style();
+        terminal::reverse_video();
+        print!("{}", &label[end..]);
+        terminal::reset_style();
+    }
+}
+
+// Offsets of parameter labels count UTF-16 code units
+fn byte_offset(text: &str, offset: u32) -> usize {
+    let mut units = 0;
+    for (index, ch) in text.char_indices() {
+        if units >= offset as usize {
+            return index;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
@@ -0,0 +1,189 @@
+use super::{Editor, List, ListAction, Pending, Prompt, PromptAction};
+use crate::language::{
+    DocumentSymbol, DocumentSymbolResult, Location, Registry, SymbolInformation,
+};
+
+// Names of SymbolKind, which starts from 1
+const KINDS: &[&str] = &[
+    "file",
+    "module",
+    "namespace",
+    "package",
+    "class",
+    "method",
+    "property",
+    "field",
+    "constructor",
+    "enum",
+    "interface",
+    "function",
+    "variable",
+    "constant",
+    "string",
+    "number",
+    "boolean",
+    "array",
+    "object",
+    "key",
+    "null",
+    "enum member",
+    "struct",
+    "event",
+    "operator",
+    "type parameter",
+];
+
+impl Editor {
+    pub(super) async fn document_symbols(&mut self, servers: &mut Registry) {
+        self.sync(servers).await;
+        let Some(client) = self.server(servers, "documentSymbolProvider") else {
+            return;
+        };
+        let id = client.document_symbol(&self.buffer().uri).await;
+        self.wait(client.id, id, Pending::DocumentSymbol(self.current));
+    }
+
+    // The outline, children indented under their parents
+    pub(super) fn show_document_symbols(&mut self, index: usize, result: DocumentSymbolResult) {
+        let mut labels = Vec::new();
+        let mut locations = Vec::new();
+        match result {
+            DocumentSymbolResult::Nested(symbols) => {
+                let uri = &self.buffers[index].uri;
+                outline(&symbols, 0, uri, &mut labels, &mut locations);
+            }
+            DocumentSymbolResult::Flat(symbols) => {
+                for symbol in symbols {
+                    labels.push(symbol_label(&symbol));
+                    locations.push(symbol.location.into());
+                }
+            }
+            DocumentSymbolResult::Null => {}
+        }
+        if locations.is_empty() {
+            return;
+        }
+        let title = format!("Symbols in {}", self.buffers[index].filepath);
+        self.show_list(List::new(title, labels), ListAction::Jump(locations));
+    }
+
+    // Queried again on every change of the input
+    pub(super) async fn workspace_symbols(&mut self, servers: &mut Registry) {
+        let Some(client) = self.server(servers, "workspaceSymbolProvider") else {
+            return;
+        };
+        let prompt = Prompt::new("Symbol".to_string(), String::new());
+        self.prompt = Some((prompt, PromptAction::WorkspaceSymbol(client.id)));
+        self.query_workspace_symbols(servers).await;
+    }
+
+    pub(super) async fn query_workspace_symbols(&mut self, servers: &mut Registry) {
+        let Some((prompt, PromptAction::WorkspaceSymbol(server))) = &self.prompt else {
+            return;
+        };
+        let (server, query) = (*server, prompt.input.clone());
+        let Some(client) = servers.get(server) else {
+            return;
+        };
+        let id = client.workspace_symbol(&query).await;
+        self.wait(server, id, Pending::WorkspaceSymbol(query));
+    }
+
+    // Ranked again here, since servers match in their own ways
+    pub(super) fn show_workspace_symbols(&mut self, query: &str, symbols: Vec<SymbolInformation>) {
+        let mut symbols: Vec<_> = symbols
+            .into_iter()
+            .filter_map(|symbol| Some((fuzzy_score(query, &symbol.name)?, symbol)))
+            .collect();
+        symbols.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+        let mut labels = Vec::new();
+        let mut locations = Vec::new();
+        for (_, symbol) in symbols {
+            let location: Location = symbol.location.clone().into();
+            labels.push(format!(
+                "{}  {}",
+                symbol_label(&symbol),
+                self.location_path(&location)
+            ));
+            locations.push(location);
+        }
+        let title = format!("{} symbols", locations.len());
+        let list = List::new(title, labels).above_prompt();
+        self.show_list(list, ListAction::Jump(locations));
+    }
+}
+
+pub(super) fn kind_name(kind: u32) -> &'static str {
+    KINDS
+        .get(kind.wrapping_sub(1) as usize)
+        .unwrap_or(&"symbol")
+}
+
+fn outline(
+    symbols: &[DocumentSymbol],
+    depth: usize,
+    uri: &str,
+    labels: &mut Vec<String>,
+    locations: &mut Vec<Location>,
+) {
+    for symbol in symbols {
+        let detail = symbol.detail.as_deref().unwrap_or_default();
+        labels.push(format!(
+            "{}{} {} {}",
+            "  ".repeat(depth),
+            kind_name(symbol.kind),
+            symbol.name,
+            detail
+        ));
+        locations.push(Location {
+            uri: uri.to_string(),
+            range: symbol.selection_range,
+        });
+        outline(&symbol.children, depth + 1, uri, labels, locations);
+    }
+}
+
+// "function name (container)"
+fn symbol_label(symbol: &SymbolInformation) -> String {
+    match &symbol.container_name {
+        Some(container) if !container.is_empty() => {
+            format!("{} {} ({})", kind_name(symbol.kind), symbol.name, container)
+        }
+        _ => format!("{} {}", kind_name(symbol.kind), symbol.name),
+    }
+}
+
+// Characters of the query in order, better when they are consecutive or start words,
+// None when they do not all appear
+fn fuzzy_score(query: &str, name: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut chars = name.chars().enumerate();
+    let mut before: Option<char> = None;
+    let mut matched: Option<usize> = None;
+    for wanted in query
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .flat_map(char::to_lowercase)
+    {
+        loop {
+            let (index, ch) = chars.next()?;
+            let word_start = before.is_none_or(|before| {
+                !before.is_alphanumeric() || (before.is_lowercase() && ch.is_uppercase())
+            });
+            before = Some(ch);
+            if ch.to_lowercase().next() != Some(wanted) {
+                continue;
+            }
+            score += 1;
+            if matched.is_some_and(|matched| matched + 1 == index) {
+                score += 4;
+            } else if word_start {
+                score += 3;
+            }
+            matched = Some(index);
+            break;
+        }
+    }
+    Some(score - name.chars().count() as i32 / 8)
+}
@@ -0,0 +1,68 @@
+use super::{Editor, List, ListAction};
+use crate::language::Registry;
+
+impl Editor {
+    // Turns the trace of the servers of the language on or off
+    pub(super) async fn toggle_trace(&mut self, servers: &mut Registry) {
+        let Some(language) = self.buffer().language.clone() else {
+            return;
+        };
+        let mut found = false;
+        let mut toggled = Vec::new();
+        for client in servers.clients(&language) {
+            found = true;
+            let level = if client.trace_level() == "off" {
+                "messages"
+            } else {
+                "off"
+            };
+            let result = client.set_trace(level).await;
+            if self.report(result).is_some() {
+                toggled.push(format!("{} {}", client.name(), level.to_uppercase()));
+            }
+        }
+        if !found {
+            self.message = Some(format!("NO LANGUAGE SERVER FOR {}", language));
+        } else if !toggled.is_empty() {
+            self.message = Some(format!("TRACE {}", toggled.join(", ")));
+        }
+    }
+
+    // The recorded messages of the servers of the language, the newest at the bottom
+    pub(super) fn show_traffic(&mut self, servers: &mut Registry) {
+        let Some(language) = self.buffer().language.clone() else {
+            return;
+        };
+        let mut lines = Vec::new();
+        for client in servers.clients(&language) {
+            let name = client.name();
+            lines.extend(
+                client
+                    .traffic()
+                    .into_iter()
+                    .map(|line| format!("{} {}", name, line)),
+            );
+        }
+        if lines.is_empty() {
+            self.message = Some("NO TRACED MESSAGES, TRACE WITH T".to_string());
+            return;
+        }
+        let mut list = List::new("Traffic".to_string(), lines.clone());
+        list.selected = lines.len() - 1;
+        self.show_list(list, ListAction::Traffic(lines));
+    }
+
+    // The whole message, one row per line of the pretty-printed JSON
+    pub(super) fn show_message_of(&mut self, line: &str) {
+        // "<server> <time> <direction> <message>"
+        let mut fields = line.splitn(4, ' ');
+        let title = fields.by_ref().take(3).collect::<Vec<_>>().join(" ");
+        let content = fields.next().unwrap_or_default();
+        let pretty = serde_json::from_str::<serde_json::Value>(content)
+            .ok()
+            .and_then(|message| serde_json::to_string_pretty(&message).ok())
+            .unwrap_or_else(|| content.to_string());
+        let list = List::new(title, pretty.lines().map(String::from).collect());
+        self.show_list(list, ListAction::View);
+    }
+}
@@ -0,0 +1,130 @@
+use serde_json::Value;
+
+use super::{Editor, List, ListAction};
+use crate::language::{Registry, ServerId};
+
+// Lines of the log kept for viewing, the oldest dropped first
+const LOG_LIMIT: usize = 1000;
+
+const SEVERITIES: [&str; 4] = ["ERROR", "WARNING", "INFO", "LOG"];
+
+// Work done by a server, reported with $/progress
+pub struct Progress {
+    pub server: ServerId,
+    token: Value,
+    name: String, // Of the server
+    title: String,
+    message: Option<String>,
+    percentage: Option<u64>,
+}
+
+impl Progress {
+    // Such as "rust-analyzer: Indexing 45% 3/8 (core)"
+    pub fn label(&self) -> String {
+        let mut label = format!("{}: {}", self.name, self.title);
+        if let Some(percentage) = self.percentage {
+            label += &format!(" {}%", percentage);
+        }
+        if let Some(message) = &self.message {
+            label += &format!(" {}", message);
+        }
+        label
+    }
+}
+
+impl Editor {
+    // Begun, reported and ended under the token of the work
+    pub(super) fn progress(&mut self, server: ServerId, params: &Value, servers: &mut Registry) {
+        let Some(client) = servers.get(server) else {
+            return;
+        };
+        let token = &params["token"];
+        let value = &params["value"];
+        let message = value["message"].as_str().map(String::from);
+        let percentage = value["percentage"].as_u64();
+        let index = self
+            .progress
+            .iter()
+            .position(|progress| progress.server == server && progress.token == *token);
+        match (value["kind"].as_str(), index) {
+            (Some("begin"), _) => {
+                self.progress
+                    .retain(|progress| !(progress.server == server && progress.token == *token));
+                self.progress.push(Progress {
+                    server,
+                    token: token.clone(),
+                    name: client.name(),
+                    title: value["title"].as_str().unwrap_or_default().to_string(),
+                    message,
+                    percentage,
+                });
+            }
+            (Some("report"), Some(index)) => {
+                let progress = &mut self.progress[index];
+                if message.is_some() {
+                    progress.message = message;
+                }
+                if percentage.is_some() {
+                    progress.percentage = percentage;
+                }
+            }
+            (Some("end"), Some(index)) => {
+                self.progress.remove(index);
+            }
+            _ => (),
+        }
+    }
+
+    // Shown on the bottom row like the messages of the editor
+    pub(super) fn show_message(
+        &mut self,
+        server: ServerId,
+        params: &Value,
+        servers: &mut Registry,
+    ) {
+        let line = self.log_line(server, params, servers);
+        self.message = Some(line.lines().next().unwrap_or_default().to_string());
+        self.log(line);
+    }
+
+    pub(super) fn log_message(&mut self, server: ServerId, params: &Value, servers: &mut Registry) {
+        let line = self.log_line(server, params, servers);
+        self.log(line);
+    }
+
+    fn log_line(&self, server: ServerId, params: &Value, servers: &mut Registry) -> String {
+        let name = servers
+            .get(server)
+            .map_or("language-server".to_string(), |client| client.name());
+        let severity = params["type"]
+            .as_u64()
+            .and_then(|severity| SEVERITIES.get((severity as usize).checked_sub(1)?))
+            .unwrap_or(&"LOG");
+        let message = params["message"].as_str().unwrap_or_default();
+        format!("{} {}: {}", name, severity, message)
+    }
+
+    fn log(&mut self, line: String) {
+        // One entry per line, as the list shows one row per item
+        self.logs.extend(line.lines().map(String::from));
+        if self.logs.len() > LOG_LIMIT {
+            self.logs.drain(..self.logs.len() - LOG_LIMIT);
+        }
+    }
+
+    // The newest at the bottom, where the list starts
+    pub(super) fn show_log(&mut self) {
+        if self.logs.is_empty() {
+            self.message = Some("NO LOG MESSAGES".to_string());
+            return;
+        }
+        let mut list = List::new("Log".to_string(), self.logs.clone());
+        list.selected = self.logs.len() - 1;
+        self.show_list(list, ListAction::View);
+    }
+
+    // The latest work still in progress
+    pub(super) fn status(&self) -> Option<String> {
+        self.progress.last().map(Progress::label)
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::Path;
+
+use super::Buffer;
+use super::Editor;
+use crate::language;
+use crate::language::{DocumentChange, Registry, ResourceOperation, TextEdit, WorkspaceEdit};
+
+impl Editor {
+    // Files without a buffer are edited on disk
+    pub(super) async fn apply_workspace_edit(
+        &mut self,
+        servers: &mut Registry,
+        edit: WorkspaceEdit,
+    ) -> Result<()> {
+        // Nothing is applied if an edit was computed for another version of an open buffer
+        for change in edit.document_changes.iter().flatten() {
+            let DocumentChange::Edit(edit) = change else {
+                continue;
+            };
+            let (Some(version), Some(filepath)) = (
+                edit.text_document.version,
+                language::uri_to_path(&edit.text_document.uri),
+            ) else {
+                continue;
+            };
+            if let Some(index) = self.find_buffer(&filepath) {
+                if self.buffers[index].version != version {
+                    bail!("STALE EDIT FOR {}", filepath);
+                }
+            }
+        }
+
+        // documentChanges wins over changes when both are sent
+        if let Some(document_changes) = edit.document_changes {
+            for change in document_changes {
+                match change {
+                    DocumentChange::Edit(edit) => {
+                        self.apply_text_edits(&edit.text_document.uri, edit.edits)?
+                    }
+                    DocumentChange::Operation(operation) => {
+                        self.apply_resource_operation(servers, operation).await?
+                    }
+                }
+            }
+        } else if let Some(changes) = edit.changes {
+            for (uri, edits) in changes {
+                self.apply_text_edits(&uri, edits)?;
+            }
+        }
+
+        for index in 0..self.buffers.len() {
+            self.sync_buffer(servers, index).await;
+        }
+        Ok(())
+    }
+
+    fn apply_text_edits(&mut self, uri: &str, edits: Vec<TextEdit>) -> Result<()> {
+        let Some(filepath) = language::uri_to_path(uri) else {
+            bail!("UNSUPPORTED URI {}", uri);
+        };
+        match self.find_buffer(&filepath) {
+            Some(index) => self.buffers[index].apply_edits(edits),
+            None => {
+                let mut buffer = Buffer::open(filepath, &self.config.languages)?;
+                buffer.apply_edits(edits);
+                buffer.save()?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_resource_operation(
+        &mut self,
+        servers: &mut Registry,
+        operation: ResourceOperation,
+    ) -> Result<()> {
+        match operation {
+            ResourceOperation::Create { uri, options } => {
+                let Some(filepath) = language::uri_to_path(&uri) else {
+                    bail!("UNSUPPORTED URI {}", uri);
+                };
+                if Path::new(&filepath).exists() && !options.overwrite {
+                    if options.ignore_if_exists {
+                        return Ok(());
+                    }
+                    bail!("FILE ALREADY EXISTS {}", filepath);
+                }
+                fs::File::create(&filepath)?;
+            }
+            ResourceOperation::Rename {
+                old_uri,
+                new_uri,
+                options,
+            } => {
+                let (Some(old_path), Some(new_path)) = (
+                    language::uri_to_path(&old_uri),
+                    language::uri_to_path(&new_uri),
+                ) else {
+                    bail!("UNSUPPORTED URI {} -> {}", old_uri, new_uri);
+                };
+                if Path::new(&new_path).exists() && !options.overwrite {
+                    if options.ignore_if_exists {
+                        return Ok(());
+                    }
+                    bail!("FILE ALREADY EXISTS {}", new_path);
+                }
+                let index = self.find_buffer(&old_path);
+                fs::rename(&old_path, &new_path)?;
+                // The language may change along with the name
+                if let Some(index) = index {
+                    self.close_document(servers, index).await?;
+                    let buffer = &mut self.buffers[index];
+                    buffer.uri = language::path_to_uri(&new_path)?;
+                    buffer.language =
+                        language::detect(&new_path, &buffer.lines, &self.config.languages);
+                    buffer.filepath = new_path;
+                    self.open_in_servers(servers, index).await;
+                }
+            }
+            ResourceOperation::Delete { uri, options } => {
+                let Some(filepath) = language::uri_to_path(&uri) else {
+                    bail!("UNSUPPORTED URI {}", uri);
+                };
+                let path = Path::new(&filepath);
+                if !path.exists() {
+                    if options.ignore_if_not_exists {
+                        return Ok(());
+                    }
+                    bail!("NO SUCH FILE {}", filepath);
+                }
+                let canonical = fs::canonicalize(path)?;
+                let deleted: Vec<usize> = (0..self.buffers.len())
+                    .filter(|index| {
+                        fs::canonicalize(&self.buffers[*index].filepath)
+                            .is_ok_and(|filepath| filepath.starts_with(&canonical))
+                    })
+                    .collect();
+                if !path.is_dir() {
+                    fs::remove_file(path)?;
+                } else if options.recursive {
+                    fs::remove_dir_all(path)?;
+                } else {
+                    fs::remove_dir(path)?;
+                }
+                // The buffers keep the text, no longer known to the servers, and can be saved again
+                for index in deleted {
+                    self.close_document(servers, index).await?;
+                    let buffer = &mut self.buffers[index];
+                    buffer.language = None;
+                    buffer.diagnostics.clear();
+                    buffer.inlay_hints.1.clear();
+                    buffer.highlights.clear();
+                    buffer.semantic_tokens = None;
+                    buffer.occurrences.clear();
+                    buffer.folding_ranges.1.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+}
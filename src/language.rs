@@ -1,139 +1,455 @@
-use anyhow::Result;
-use nix::unistd::{close, dup2, execv, fork, pipe, ForkResult};
+mod detection;
+mod error;
+mod registry;
+mod trace;
+mod types;
+mod uri;
+mod workspace;
+
+pub use detection::*;
+pub use error::*;
+pub use registry::*;
+pub use trace::*;
+pub use types::*;
+pub use uri::*;
+pub use workspace::*;
+
+use anyhow::{bail, Result};
 use serde_json::json;
 use std::collections::HashMap;
-use std::ffi::CString;
-use std::os::unix::prelude::*;
-use std::sync::{Arc, Mutex};
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command as Process};
 use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time;
 
+use crate::config::{self, ServerConfig};
 use crate::message::*;
 
+const MAX_HEADER_LINE_LENGTH: u64 = 1024;
+const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const UNTIMED_METHODS: &[&str] = &["initialize", "shutdown"]; // Slow on large workspaces, never cancelled
+const RESTART_DELAY: Duration = Duration::from_secs(1); // Doubled on every attempt
+const RESTART_LIMIT: u32 = 5;
+const STABLE_UPTIME: Duration = Duration::from_secs(60); // Resets the attempts
+
+// The stdin of the server, with the trace of what is written to it
+#[derive(Clone)]
+struct Writer {
+    stream: Arc<tokio::sync::Mutex<BufWriter<ChildStdin>>>,
+    trace: Tracer,
+}
+
+type Unreturned = Arc<Mutex<HashMap<Id, oneshot::Sender<Option<Response>>>>>; // None if closed
+
 pub struct Client {
-    request_writer: BufWriter<File>,
-    unreturned: Arc<Mutex<HashMap<i32, oneshot::Sender<Response>>>>,
+    pub id: ServerId,
+    pub language: String, // Language ID of the documents the server is for
+    config: ServerConfig,
+    root: PathBuf, // Workspace folder
+    request_writer: Writer,
+    event_sender: mpsc::UnboundedSender<Event>,
+    unreturned: Unreturned,
+    next_id: AtomicI64,
+    latest: HashMap<String, Id>, // Latest request per method and document
+    pub capabilities: serde_json::Value, // Server capabilities
+    ready: bool,                 // Whether the server answered initialize
+    pid: u32,
+    started: Instant,
+    restarts: u32, // Automatic restarts since the server last ran stably
+    listener: JoinHandle<()>,
+    _kill_sender: oneshot::Sender<()>, // Dropping it kills the server
+    trace: Tracer,                     // Kept across restarts
 }
 
+// A running server, with its end of the pipes handled by spawned tasks
+struct Server {
+    request_writer: Writer,
+    unreturned: Unreturned,
+    pid: u32,
+    listener: JoinHandle<()>,
+    kill_sender: oneshot::Sender<()>,
+}
+
+// The handshake finishes with Client::initialized once the response comes back
 pub async fn initialize(
-    path: String,
+    id: ServerId,
+    language: String,
+    config: ServerConfig,
+    root: PathBuf,
     event_sender: mpsc::UnboundedSender<Event>,
-) -> Result<Client> {
-    let mut client = Client::new(path, event_sender).await?;
-    let _response = client
-        .request(Request::new("initialize", json!({"capabilities": {}})))
-        .await?;
-    // PARSE RESPONSE (SUCH AS TO GET TRIGGER CHARACTERS)
-    client
-        .notify(Notification::new("initialized", json!({})))
-        .await?;
-    Ok(client)
+) -> Result<(Client, Id)> {
+    let mut client = Client::new(id, language, config, root, event_sender)?;
+    let initialize = client.initialize().await?;
+    Ok((client, initialize))
 }
 
-impl Client {
-    pub async fn new(path: String, event_sender: mpsc::UnboundedSender<Event>) -> Result<Client> {
-        let response_pipe = pipe()?;
-        let request_pipe = pipe()?;
-
-        match unsafe { fork()? } {
-            ForkResult::Child => {
-                close(request_pipe.1)?;
-                close(response_pipe.0)?;
-                dup2(request_pipe.0, 0)?;
-                dup2(response_pipe.1, 1)?;
+fn capabilities() -> serde_json::Value {
+    json!({
+        "window": {
+            "workDoneProgress": true,
+            "showMessage": {
+                "messageActionItem": { "additionalPropertiesSupport": false },
+            },
+        },
+        "textDocument": {
+            "definition": { "linkSupport": true },
+            "declaration": { "linkSupport": true },
+            "typeDefinition": { "linkSupport": true },
+            "implementation": { "linkSupport": true },
+            "references": {},
+            "rename": { "prepareSupport": true },
+            "publishDiagnostics": {},
+            "formatting": {},
+            "rangeFormatting": {},
+            "inlayHint": {},
+            "documentHighlight": {},
+            "callHierarchy": {},
+            "foldingRange": { "lineFoldingOnly": true },
+            "typeHierarchy": {},
+            "semanticTokens": {
+                "requests": { "full": { "delta": true } },
+                "tokenTypes": [
+                    "namespace", "type", "class", "enum", "interface", "struct",
+                    "typeParameter", "parameter", "variable", "property", "enumMember",
+                    "event", "function", "method", "macro", "keyword", "modifier",
+                    "comment", "string", "number", "regexp", "operator", "decorator",
+                ],
+                "tokenModifiers": [
+                    "declaration", "definition", "readonly", "static", "deprecated",
+                    "abstract", "async", "modification", "documentation", "defaultLibrary",
+                ],
+                "formats": ["relative"],
+                "overlappingTokenSupport": false,
+                "multilineTokenSupport": false,
+            },
+            "documentSymbol": { "hierarchicalDocumentSymbolSupport": true },
+            "signatureHelp": {
+                "signatureInformation": {
+                    "parameterInformation": { "labelOffsetSupport": true },
+                    "activeParameterSupport": true,
+                },
+                "contextSupport": true,
+            },
+            "codeAction": {
+                "codeActionLiteralSupport": {
+                    "codeActionKind": {
+                        "valueSet": [
+                            "",
+                            "quickfix",
+                            "refactor",
+                            "refactor.extract",
+                            "refactor.inline",
+                            "refactor.rewrite",
+                            "source",
+                            "source.organizeImports",
+                        ],
+                    },
+                },
+                "disabledSupport": true,
+            },
+        },
+        "workspace": {
+            "workspaceFolders": true,
+            "symbol": {},
+            "inlayHint": { "refreshSupport": true },
+            "semanticTokens": { "refreshSupport": true },
+            "applyEdit": true,
+            "configuration": true,
+            "executeCommand": {},
+            "workspaceEdit": {
+                "documentChanges": true,
+                "resourceOperations": ["create", "rename", "delete"],
+            },
+        },
+    })
+}
 
-                let path = CString::new(path).expect("CSTRING NEW FAILED");
-                execv(&path, &[&path])?;
+fn formatting_options() -> serde_json::Value {
+    json!({
+        "tabSize": 4,
+        "insertSpaces": true,
+        "trimTrailingWhitespace": true,
+        "insertFinalNewline": true,
+        "trimFinalNewlines": true,
+    })
+}
 
-                panic!("UNEXPECTED SERVER TERMINATION")
-            }
+impl Client {
+    // Capabilities are either a boolean or an options object
+    pub fn supports(&self, capability: &str) -> bool {
+        match &self.capabilities[capability] {
+            serde_json::Value::Bool(supported) => *supported,
+            serde_json::Value::Object(_) => true,
+            _ => false,
+        }
+    }
 
-            ForkResult::Parent { child: _ } => {
-                close(request_pipe.0)?;
-                close(response_pipe.1)?;
+    pub fn new(
+        id: ServerId,
+        language: String,
+        config: ServerConfig,
+        root: PathBuf,
+        event_sender: mpsc::UnboundedSender<Event>,
+    ) -> Result<Client> {
+        let trace = Trace::new(server_name(&config.command));
+        if let Some(level) = &config.trace {
+            lock_trace(&trace).set_level(level)?;
+        }
+        let server = spawn(id, &config, &root, &event_sender, &trace)?;
+        Ok(Client {
+            id,
+            language,
+            config,
+            root,
+            request_writer: server.request_writer,
+            event_sender,
+            unreturned: server.unreturned,
+            next_id: AtomicI64::new(1),
+            latest: HashMap::new(),
+            capabilities: serde_json::Value::Null,
+            ready: false,
+            pid: server.pid,
+            started: Instant::now(),
+            restarts: 0,
+            listener: server.listener,
+            _kill_sender: server.kill_sender,
+            trace,
+        })
+    }
 
-                let request_channel = unsafe { File::from_raw_fd(request_pipe.1) };
-                let response_channel = unsafe { File::from_raw_fd(response_pipe.0) };
-                let unreturned = Arc::new(Mutex::new(HashMap::new()));
+    // The response comes back as Event::LanguageResponse
+    async fn initialize(&mut self) -> Result<Id> {
+        let mut params = json!({
+            "processId": std::process::id(),
+            "rootUri": path_to_uri(&self.root.to_string_lossy())?,
+            "workspaceFolders": self.workspace_folders()?,
+            "capabilities": capabilities(),
+            "trace": self.trace_level(),
+        });
+        if let Some(options) = &self.config.initialization_options {
+            params["initializationOptions"] = options.clone();
+        }
+        self.spawn_request("initialize", params).await
+    }
 
-                tokio::spawn(listen(
-                    BufReader::new(response_channel),
-                    event_sender.clone(),
-                    unreturned.clone(),
-                ));
+    pub async fn initialized(&mut self, result: serde_json::Value) -> Result<()> {
+        self.capabilities = result["capabilities"].clone();
+        self.ready = true;
+        self.notify(Notification::new("initialized", json!({})))
+            .await?;
 
-                Ok(Client {
-                    request_writer: BufWriter::new(request_channel),
-                    unreturned,
-                })
-            }
+        // Servers not asking workspace/configuration pick the settings up from here
+        if !self.config.settings.is_null() {
+            let settings = self.config.settings.clone();
+            self.notify(Notification::new(
+                "workspace/didChangeConfiguration",
+                json!({ "settings": settings }),
+            ))
+            .await?;
         }
+        Ok(())
     }
 
-    pub async fn request(&mut self, content: Request) -> Result<Response> {
-        let (response_sender, response_receiver) = oneshot::channel();
+    pub fn workspace_folders(&self) -> io::Result<serde_json::Value> {
+        let name = self
+            .root
+            .file_name()
+            .map_or("/".into(), |name| name.to_string_lossy());
+        Ok(json!([{
+            "uri": path_to_uri(&self.root.to_string_lossy())?,
+            "name": name,
+        }]))
+    }
 
-        match self.unreturned.lock() {
-            Ok(mut unreturned) => {
-                unreturned.insert(content.id, response_sender);
-            }
-            Err(err) => {
-                panic!("UNRETURNED REQUEST POOL LOCK FAILED\n{}", err);
-            }
+    // The settings under a dotted section such as "rust-analyzer.cargo", all without one
+    pub fn settings(&self, section: Option<&str>) -> serde_json::Value {
+        let mut settings = &self.config.settings;
+        for key in section.into_iter().flat_map(|section| section.split('.')) {
+            settings = &settings[key];
         }
+        settings.clone()
+    }
 
-        match serde_json::to_vec(&content) {
-            Ok(msg) => {
-                self.send(msg).await?;
-            }
-            Err(err) => {
-                panic!("NOTIFICATION SERIALIZE FAILED\n{}", err);
-            }
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    // Replaces the server process, closing the requests the old one did not answer
+    pub async fn restart(&mut self) -> Result<Id> {
+        self.listener.abort();
+        close(&self.unreturned);
+
+        let server = spawn(
+            self.id,
+            &self.config,
+            &self.root,
+            &self.event_sender,
+            &self.trace,
+        )?;
+        self.request_writer = server.request_writer;
+        self.unreturned = server.unreturned;
+        self.pid = server.pid;
+        self.listener = server.listener;
+        self._kill_sender = server.kill_sender;
+        self.latest.clear();
+        self.capabilities = serde_json::Value::Null;
+        self.ready = false;
+        self.started = Instant::now();
+        self.initialize().await
+    }
+
+    // Sends Event::LanguageServerRestart after a delay doubling with each attempt,
+    // None once the attempts are used up
+    pub fn schedule_restart(&mut self) -> Option<Duration> {
+        if self.started.elapsed() >= STABLE_UPTIME {
+            self.restarts = 0;
+        }
+        if self.restarts >= RESTART_LIMIT {
+            return None;
         }
+        let delay = RESTART_DELAY * 2u32.pow(self.restarts);
+        self.restarts += 1;
 
-        match response_receiver.await {
-            Ok(response) => Ok(response),
-            Err(err) => {
-                panic!("RESPONSE LISTENER HAS ALREADY CLOSED\n{}", err);
+        let event_sender = self.event_sender.clone();
+        let (id, pid) = (self.id, self.pid);
+        tokio::spawn(async move {
+            time::sleep(delay).await;
+            let _ = event_sender.send(Event::LanguageServerRestart(id, pid));
+        });
+        Some(delay)
+    }
+
+    pub fn trace_level(&self) -> String {
+        lock_trace(&self.trace).level().to_string()
+    }
+
+    // Records the traffic from now on, or stops, and asks the server to trace as much
+    pub async fn set_trace(&mut self, level: &str) -> Result<()> {
+        lock_trace(&self.trace).set_level(level)?;
+        self.notify(Notification::new("$/setTrace", json!({ "value": level })))
+            .await
+    }
+
+    // The latest messages recorded while tracing
+    pub fn traffic(&self) -> Vec<String> {
+        lock_trace(&self.trace).recent()
+    }
+
+    // Restarting by hand starts counting the attempts over
+    pub async fn restart_manually(&mut self) -> Result<Id> {
+        self.restarts = 0;
+        self.restart().await
+    }
+
+    // Sends the request, leaving the response to be awaited
+    async fn start_request(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<(Id, oneshot::Receiver<Option<Response>>)> {
+        let id = Id::Number(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        // A newer request about the same document supersedes the older one
+        if let Some(uri) = params["textDocument"]["uri"].as_str() {
+            let key = format!("{} {}", method, uri);
+            if let Some(older) = self.latest.insert(key, id.clone()) {
+                self.cancel(&older).await?;
             }
         }
+
+        let content = Request::new(id.clone(), method, params);
+        let (response_sender, response_receiver) = oneshot::channel();
+
+        lock(&self.unreturned).insert(content.id.clone(), response_sender);
+
+        self.send(&ClientMessage::Request(content)).await?;
+        Ok((id, response_receiver))
+    }
+
+    pub async fn request(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let (id, response_receiver) = self.start_request(method, params).await?;
+        Ok(wait_response(
+            &self.request_writer,
+            &self.unreturned,
+            id,
+            method,
+            response_receiver,
+        )
+        .await?)
+    }
+
+    // The response comes back later as Event::LanguageResponse
+    pub async fn spawn_request(&mut self, method: &str, params: serde_json::Value) -> Result<Id> {
+        let (id, response_receiver) = self.start_request(method, params).await?;
+        let request_writer = self.request_writer.clone();
+        let unreturned = self.unreturned.clone();
+        let event_sender = self.event_sender.clone();
+        let server = self.id;
+        let method = method.to_string();
+        let spawned_id = id.clone();
+        tokio::spawn(async move {
+            let response = wait_response(
+                &request_writer,
+                &unreturned,
+                spawned_id.clone(),
+                &method,
+                response_receiver,
+            )
+            .await;
+            let _ = event_sender.send(Event::LanguageResponse(server, spawned_id, response));
+        });
+        Ok(id)
+    }
+
+    // Forgets the request and tells the server, if it is not answered yet
+    pub async fn cancel(&mut self, id: &Id) -> Result<()> {
+        cancel_request(&self.request_writer, &self.unreturned, id).await
     }
 
+    pub async fn respond(&mut self, response: Response) -> Result<()> {
+        self.send(&ClientMessage::Response(response)).await
+    }
+
+    // Until the server is initialized only exit may be sent, the documents are opened after that
     pub async fn notify(&mut self, content: Notification) -> Result<()> {
-        match serde_json::to_vec(&content) {
-            Ok(msg) => {
-                self.send(msg).await?;
-            }
-            Err(err) => panic!("NOTIFICATION SERIALIZE FAILED\n{}", err),
+        if !self.ready && content.method != "exit" {
+            return Ok(());
         }
-        Ok(())
+        self.send(&ClientMessage::Notification(content)).await
     }
 
-    async fn send(&mut self, content: Vec<u8>) -> Result<()> {
-        let header = format!("Content-Length: {}\r\n\r\n", content.len());
-        self.request_writer.write(header.as_bytes()).await?;
-        self.request_writer.write(&content).await?;
-        self.request_writer.flush().await?;
-        Ok(())
+    async fn send(&mut self, content: &ClientMessage) -> Result<()> {
+        send(&self.request_writer, content).await
     }
 
     pub async fn shutdown(&mut self) -> Result<()> {
-        self.request(Request::new("shutdown", serde_json::Value::Null))
-            .await?;
+        self.request("shutdown", serde_json::Value::Null).await?;
         self.notify(Notification::new("exit", serde_json::Value::Null))
             .await?;
         Ok(())
     }
 
-    pub async fn did_open(&mut self, language_id: &str, uri: &str, text: &str) -> Result<()> {
+    pub async fn did_open(&mut self, uri: &str, version: i32, text: &str) -> Result<()> {
         self.notify(Notification::new(
             "textDocument/didOpen",
             json!({
                 "textDocument": {
                     "uri": uri,
-                    "languageId": language_id,
-                    "version": 1,
+                    "languageId": self.language,
+                    "version": version,
                     "text": text,
                 }
             }),
@@ -141,6 +457,20 @@ impl Client {
         .await
     }
 
+    pub async fn did_change(&mut self, uri: &str, version: i32, text: &str) -> Result<()> {
+        self.notify(Notification::new(
+            "textDocument/didChange",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "version": version,
+                },
+                "contentChanges": [{ "text": text }],
+            }),
+        ))
+        .await
+    }
+
     pub async fn did_close(&mut self, uri: &str) -> Result<()> {
         self.notify(Notification::new(
             "textDocument/didClose",
@@ -152,52 +482,550 @@ impl Client {
         ))
         .await
     }
+
+    // textDocument/definition, declaration, typeDefinition and implementation
+    pub async fn goto(&mut self, method: &str, uri: &str, position: Position) -> Result<Id> {
+        self.spawn_request(
+            method,
+            json!({
+                "textDocument": { "uri": uri },
+                "position": position,
+            }),
+        )
+        .await
+    }
+
+    pub async fn references(&mut self, uri: &str, position: Position) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/references",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": position,
+                "context": { "includeDeclaration": true },
+            }),
+        )
+        .await
+    }
+
+    pub async fn prepare_rename(&mut self, uri: &str, position: Position) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/prepareRename",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": position,
+            }),
+        )
+        .await
+    }
+
+    pub async fn rename(&mut self, uri: &str, position: Position, new_name: &str) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/rename",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": position,
+                "newName": new_name,
+            }),
+        )
+        .await
+    }
+
+    pub async fn code_action(
+        &mut self,
+        uri: &str,
+        range: Range,
+        diagnostics: &[Diagnostic],
+    ) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": range,
+                "context": { "diagnostics": diagnostics },
+            }),
+        )
+        .await
+    }
+
+    pub async fn execute_command(&mut self, command: &Command) -> Result<Id> {
+        let mut params = json!({ "command": command.command });
+        if let Some(arguments) = &command.arguments {
+            params["arguments"] = json!(arguments);
+        }
+        self.spawn_request("workspace/executeCommand", params).await
+    }
+
+    pub async fn formatting(&mut self, uri: &str) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/formatting",
+            json!({
+                "textDocument": { "uri": uri },
+                "options": formatting_options(),
+            }),
+        )
+        .await
+    }
+
+    pub async fn range_formatting(&mut self, uri: &str, range: Range) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/rangeFormatting",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": range,
+                "options": formatting_options(),
+            }),
+        )
+        .await
+    }
+
+    pub async fn signature_help(
+        &mut self,
+        uri: &str,
+        position: Position,
+        context: serde_json::Value,
+    ) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/signatureHelp",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": position,
+                "context": context,
+            }),
+        )
+        .await
+    }
+
+    pub async fn document_symbol(&mut self, uri: &str) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+    }
+
+    pub async fn inlay_hint(&mut self, uri: &str, range: Range) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/inlayHint",
+            json!({
+                "textDocument": { "uri": uri },
+                "range": range,
+            }),
+        )
+        .await
+    }
+
+    pub async fn document_highlight(&mut self, uri: &str, position: Position) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/documentHighlight",
+            json!({
+                "textDocument": { "uri": uri },
+                "position": position,
+            }),
+        )
+        .await
+    }
+
+    pub async fn folding_range(&mut self, uri: &str) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/foldingRange",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+    }
+
+    // textDocument/prepareCallHierarchy and prepareTypeHierarchy
+    pub async fn prepare_hierarchy(
+        &mut self,
+        method: &str,
+        uri: &str,
+        position: Position,
+    ) -> Result<Id> {
+        self.spawn_request(
+            method,
+            json!({
+                "textDocument": { "uri": uri },
+                "position": position,
+            }),
+        )
+        .await
+    }
+
+    // The calls or types next to the item, which goes back to the server as it came
+    pub async fn hierarchy(&mut self, method: &str, item: &serde_json::Value) -> Result<Id> {
+        self.spawn_request(method, json!({ "item": item })).await
+    }
+
+    pub async fn semantic_tokens_full(&mut self, uri: &str) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/semanticTokens/full",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await
+    }
+
+    // Changes since the tokens of the result
+    pub async fn semantic_tokens_delta(&mut self, uri: &str, result_id: &str) -> Result<Id> {
+        self.spawn_request(
+            "textDocument/semanticTokens/full/delta",
+            json!({
+                "textDocument": { "uri": uri },
+                "previousResultId": result_id,
+            }),
+        )
+        .await
+    }
+
+    pub fn name(&self) -> String {
+        server_name(&self.config.command)
+    }
+
+    // Token types of the legend, indexed by the tokens
+    pub fn semantic_token_types(&self) -> Vec<String> {
+        self.capabilities["semanticTokensProvider"]["legend"]["tokenTypes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|token_type| token_type.as_str().map(String::from))
+            .collect()
+    }
+
+    pub async fn workspace_symbol(&mut self, query: &str) -> Result<Id> {
+        self.spawn_request("workspace/symbol", json!({ "query": query }))
+            .await
+    }
+
+    // Characters of signatureHelpProvider such as "(" and ","
+    pub fn signature_help_triggers(&self, retrigger: bool) -> Vec<char> {
+        let provider = &self.capabilities["signatureHelpProvider"];
+        let mut keys = vec!["triggerCharacters"];
+        if retrigger {
+            keys.push("retriggerCharacters");
+        }
+        keys.iter()
+            .filter_map(|key| provider[key].as_array())
+            .flatten()
+            .filter_map(|character| character.as_str()?.chars().next())
+            .collect()
+    }
+}
+
+// The server runs in the workspace folder unless the configuration says otherwise
+fn spawn(
+    id: ServerId,
+    config: &ServerConfig,
+    root: &Path,
+    event_sender: &mpsc::UnboundedSender<Event>,
+    trace: &Tracer,
+) -> Result<Server> {
+    let mut process = Process::new(&config.command);
+    process
+        .args(&config.args)
+        .envs(&config.env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(log_file(&config.command)?)
+        .kill_on_drop(true);
+    process.current_dir(config.cwd.as_deref().map_or(root, Path::new));
+    let mut child = process.spawn()?;
+    let (Some(request_channel), Some(response_channel), Some(pid)) =
+        (child.stdin.take(), child.stdout.take(), child.id())
+    else {
+        bail!("LANGUAGE SERVER NOT STARTED {}", config.command);
+    };
+
+    let unreturned = Arc::new(Mutex::new(HashMap::new()));
+    let listener = tokio::spawn(listen(
+        BufReader::new(response_channel),
+        id,
+        event_sender.clone(),
+        unreturned.clone(),
+        trace.clone(),
+    ));
+    let (kill_sender, kill_receiver) = oneshot::channel();
+    tokio::spawn(supervise(id, child, kill_receiver, event_sender.clone()));
+
+    Ok(Server {
+        request_writer: Writer {
+            stream: Arc::new(tokio::sync::Mutex::new(BufWriter::new(request_channel))),
+            trace: trace.clone(),
+        },
+        unreturned,
+        pid,
+        listener,
+        kill_sender,
+    })
+}
+
+// The file name of the command
+fn server_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or("language-server".into(), |name| {
+            name.to_string_lossy().into_owned()
+        })
+}
+
+// stderr of the server goes to $XDG_STATE_HOME/sura/<server>.log
+fn log_file(path: &str) -> Result<fs::File> {
+    let log_path = config::state_dir()?.join(format!("{}.log", server_name(path)));
+    Ok(fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?)
+}
+
+// Reaps the server, reporting exits the client did not ask for
+async fn supervise(
+    id: ServerId,
+    mut child: Child,
+    kill_receiver: oneshot::Receiver<()>,
+    event_sender: mpsc::UnboundedSender<Event>,
+) {
+    let pid = child.id().unwrap_or_default();
+    tokio::select! {
+        status = child.wait() => {
+            let status = match status {
+                Ok(status) => status.to_string(),
+                Err(err) => err.to_string(),
+            };
+            let _ = event_sender.send(Event::LanguageServerExit(id, pid, status));
+        }
+        _ = kill_receiver => {
+            let _ = child.kill().await;
+        }
+    }
+}
+
+// A panic while holding the lock does not break the pool itself
+fn lock(unreturned: &Unreturned) -> MutexGuard<'_, HashMap<Id, oneshot::Sender<Option<Response>>>> {
+    unreturned.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Nobody answers the requests anymore
+fn close(unreturned: &Unreturned) {
+    for (_, sender) in lock(unreturned).drain() {
+        let _ = sender.send(None);
+    }
+}
+
+async fn send(request_writer: &Writer, content: &ClientMessage) -> Result<()> {
+    let content = serde_json::to_vec(content)?;
+    let header = format!("Content-Length: {}\r\n\r\n", content.len());
+    let mut stream = request_writer.stream.lock().await;
+    // Under the lock of the stream, so that the trace is in the order of writing
+    lock_trace(&request_writer.trace).record(Direction::Sent, &content);
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(&content).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn wait_response(
+    request_writer: &Writer,
+    unreturned: &Unreturned,
+    id: Id,
+    method: &str,
+    response_receiver: oneshot::Receiver<Option<Response>>,
+) -> Result<serde_json::Value, Error> {
+    let response = if UNTIMED_METHODS.contains(&method) {
+        Ok(response_receiver.await)
+    } else {
+        time::timeout(REQUEST_TIMEOUT, response_receiver).await
+    };
+    match response {
+        Ok(Ok(Some(response))) => response
+            .into_result()
+            .map_err(|error| Error::Response(method.to_string(), error)),
+        Ok(Ok(None)) => Err(Error::Closed(method.to_string())),
+        Ok(Err(_)) => Err(Error::Cancelled(method.to_string())),
+        Err(_) => match cancel_request(request_writer, unreturned, &id).await {
+            Ok(_) => Err(Error::TimedOut(method.to_string())),
+            Err(_) => Err(Error::Closed(method.to_string())),
+        },
+    }
+}
+
+async fn cancel_request(request_writer: &Writer, unreturned: &Unreturned, id: &Id) -> Result<()> {
+    let pending = lock(unreturned).remove(id).is_some();
+    if pending {
+        let notification = Notification::new("$/cancelRequest", json!({ "id": id }));
+        send(request_writer, &ClientMessage::Notification(notification)).await?;
+    }
+    Ok(())
 }
 
 async fn listen(
-    mut response_reader: BufReader<File>,
+    mut response_reader: BufReader<ChildStdout>,
+    id: ServerId,
     event_sender: mpsc::UnboundedSender<Event>,
-    unreturned: Arc<Mutex<HashMap<i32, oneshot::Sender<Response>>>>,
-) -> Result<()> {
+    unreturned: Unreturned,
+    trace: Tracer,
+) {
+    let err = receive(&mut response_reader, id, &event_sender, &unreturned, &trace).await;
+
+    close(&unreturned);
+    let _ = event_sender.send(Event::LanguageError(err));
+}
+
+// Returns when the stream is broken
+async fn receive(
+    response_reader: &mut BufReader<ChildStdout>,
+    id: ServerId,
+    event_sender: &mpsc::UnboundedSender<Event>,
+    unreturned: &Unreturned,
+    trace: &Tracer,
+) -> anyhow::Error {
     loop {
-        let msg = read_response(&mut response_reader).await?;
-        match serde_json::from_slice::<ServerMessage>(&msg)? {
-            ServerMessage::Response(response) => match unreturned.lock() {
-                Ok(mut unreturned) => {
-                    if let Some(sender) = unreturned.remove(&response.id) {
-                        if let Err(_) = sender.send(response) {
-                            panic!("RESPONSE RECEIVER HAS ALREADY CLOSED");
-                        }
-                    } else {
-                        panic!("NO RESPONSE SENDER IN UNRETURNED REQUEST POOL");
-                    }
+        let msg = match read_response(response_reader).await {
+            Ok(msg) => msg,
+            Err(err) => return err,
+        };
+        lock_trace(trace).record(Direction::Received, &msg);
+        let event = match serde_json::from_slice::<ServerMessage>(&msg) {
+            Ok(ServerMessage::Response(response)) => {
+                // Responses to cancelled or timed out requests are stale
+                if let Some(sender) = lock(unreturned).remove(&response.id.normalize()) {
+                    let _ = sender.send(Some(response));
                 }
-                Err(_) => panic!("UNRETURNED REQUEST POOL LOCK FAILED"),
-            },
-            ServerMessage::Notification(notification) => {
-                event_sender.send(Event::LanguageNotification(notification))?;
+                continue;
             }
+            Ok(ServerMessage::Request(request)) => Event::LanguageRequest(id, request),
+            Ok(ServerMessage::Notification(notification)) => {
+                Event::LanguageNotification(id, notification)
+            }
+            // The frame itself was fine, so the following messages can still be read
+            Err(err) => Event::LanguageError(err.into()),
+        };
+        if let Err(err) = event_sender.send(event) {
+            return err.into();
         }
     }
 }
 
-async fn read_response(response_reader: &mut BufReader<File>) -> Result<Vec<u8>> {
+async fn read_response<R: AsyncBufRead + Unpin>(response_reader: &mut R) -> Result<Vec<u8>> {
     let content_length = read_response_header(response_reader).await?;
     let mut buf = vec![0; content_length];
     response_reader.read_exact(&mut buf).await?;
     Ok(buf)
 }
 
-async fn read_response_header(response_reader: &mut BufReader<File>) -> Result<usize> {
-    // TODO: handle Content-Type
-    let mut content_length = 0;
-    let mut buf = [0; 16];
-    response_reader.read_exact(&mut buf).await?; // "Content-Length: "
-    response_reader.read(&mut buf[0..1]).await?;
-    while b'0' <= buf[0] && buf[0] <= b'9' {
-        content_length = content_length * 10 + (buf[0] - 48) as usize;
-        response_reader.read(&mut buf[0..1]).await?;
+// Base protocol header: "Name: value" fields terminated by "\r\n", then an empty line
+async fn read_response_header<R: AsyncBufRead + Unpin>(response_reader: &mut R) -> Result<usize> {
+    let mut content_length = None;
+    loop {
+        let mut line = Vec::new();
+        (&mut *response_reader)
+            .take(MAX_HEADER_LINE_LENGTH)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if line.is_empty() {
+            bail!("LANGUAGE SERVER CLOSED THE STREAM");
+        }
+        let Some(line) = line.strip_suffix(b"\r\n") else {
+            bail!("MALFORMED HEADER LINE {:?}", String::from_utf8_lossy(&line));
+        };
+        if line.is_empty() {
+            break;
+        }
+        let line = std::str::from_utf8(line)?;
+        let Some((name, value)) = line.split_once(':') else {
+            bail!("MALFORMED HEADER FIELD {:?}", line);
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            if content_length.is_some() {
+                bail!("DUPLICATE CONTENT-LENGTH HEADER");
+            }
+            content_length = Some(value.parse::<usize>()?);
+        } else if name.eq_ignore_ascii_case("Content-Type") {
+            check_content_type(value)?;
+        }
+    }
+
+    let Some(content_length) = content_length else {
+        bail!("NO CONTENT-LENGTH HEADER");
+    };
+    if content_length > MAX_CONTENT_LENGTH {
+        bail!("TOO LARGE MESSAGE ({} BYTES)", content_length);
     }
-    response_reader.read_exact(&mut buf[0..3]).await?; // \n\r\n
     Ok(content_length)
 }
+
+// Only UTF-8 is allowed ("utf8" is accepted for backwards compatibility)
+fn check_content_type(value: &str) -> Result<()> {
+    for parameter in value.split(';').skip(1) {
+        let Some((name, charset)) = parameter.split_once('=') else {
+            continue;
+        };
+        let charset = charset.trim().trim_matches('"');
+        if name.trim().eq_ignore_ascii_case("charset")
+            && !charset.eq_ignore_ascii_case("utf-8")
+            && !charset.eq_ignore_ascii_case("utf8")
+        {
+            bail!("UNSUPPORTED CHARSET {}", charset);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(input: &str) -> Result<Vec<u8>> {
+        read_response(&mut input.as_bytes()).await
+    }
+
+    #[tokio::test]
+    async fn reads_content_length() {
+        assert_eq!(read("Content-Length: 2\r\n\r\n{}").await.unwrap(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn accepts_reordered_headers_and_content_type() {
+        let input = "Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n\
+                     Content-Length: 2\r\n\r\n{}";
+        assert_eq!(read(input).await.unwrap(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn ignores_the_case_of_names() {
+        let input =
+            "content-length: 2\r\nCONTENT-TYPE: application/vscode-jsonrpc; charset=utf8\r\n\r\n{}";
+        assert_eq!(read(input).await.unwrap(), b"{}");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_missing_terminator() {
+        assert!(read("Content-Length: 2\n\n{}").await.is_err());
+        assert!(read("Content-Length: 2\r\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_a_duplicate_content_length() {
+        let input = "Content-Length: 2\r\nContent-Length: 2\r\n\r\n{}";
+        let err = read(input).await.unwrap_err();
+        assert_eq!(err.to_string(), "DUPLICATE CONTENT-LENGTH HEADER");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_oversize_content_length() {
+        let input = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1);
+        let err = read(&input).await.unwrap_err();
+        assert!(err.to_string().starts_with("TOO LARGE MESSAGE"));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_bad_charset() {
+        let input = "Content-Length: 2\r\nContent-Type: text/plain; charset=\"latin1\"\r\n\r\n{}";
+        let err = read(input).await.unwrap_err();
+        assert_eq!(err.to_string(), "UNSUPPORTED CHARSET latin1");
+    }
+
+    #[test]
+    fn accepts_utf8_charsets() {
+        assert!(check_content_type("application/vscode-jsonrpc").is_ok());
+        assert!(check_content_type("application/vscode-jsonrpc; charset=UTF-8").is_ok());
+        assert!(check_content_type("application/vscode-jsonrpc; charset=\"utf8\"").is_ok());
+    }
+}
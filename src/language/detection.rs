@@ -0,0 +1,297 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::LanguageConfig;
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("cxx", "cpp"),
+    ("hh", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("go", "go"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("swift", "swift"),
+    ("zig", "zig"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("pl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("jsx", "javascriptreact"),
+    ("ts", "typescript"),
+    ("tsx", "typescriptreact"),
+    ("hs", "haskell"),
+    ("ml", "ocaml"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("erl", "erlang"),
+    ("sh", "shellscript"),
+    ("bash", "shellscript"),
+    ("zsh", "shellscript"),
+    ("html", "html"),
+    ("css", "css"),
+    ("json", "json"),
+    ("toml", "toml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("md", "markdown"),
+    ("sql", "sql"),
+];
+
+const FILE_NAMES: &[(&str, &str)] = &[
+    ("Makefile", "makefile"),
+    ("GNUmakefile", "makefile"),
+    ("Dockerfile", "dockerfile"),
+    ("CMakeLists.txt", "cmake"),
+    ("Cargo.lock", "toml"),
+    ("Gemfile", "ruby"),
+    ("Rakefile", "ruby"),
+    (".bashrc", "shellscript"),
+    (".bash_profile", "shellscript"),
+    (".profile", "shellscript"),
+    (".zshrc", "shellscript"),
+];
+
+// Programs named on shebang lines, without a version suffix
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("sh", "shellscript"),
+    ("bash", "shellscript"),
+    ("dash", "shellscript"),
+    ("zsh", "shellscript"),
+    ("python", "python"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("php", "php"),
+    ("lua", "lua"),
+    ("node", "javascript"),
+    ("deno", "typescript"),
+];
+
+// Modeline names which are not LSP language IDs
+const ALIASES: &[(&str, &str)] = &[
+    ("sh", "shellscript"),
+    ("bash", "shellscript"),
+    ("zsh", "shellscript"),
+    ("js", "javascript"),
+    ("ts", "typescript"),
+    ("py", "python"),
+    ("rs", "rust"),
+    ("c++", "cpp"),
+    ("cs", "csharp"),
+    ("make", "makefile"),
+    ("yml", "yaml"),
+];
+
+// Lines searched for a modeline at each end of the file
+const MODELINE_LINES: usize = 5;
+
+// The LSP language ID of the file, from its modeline, shebang, file name or extension
+pub fn detect(
+    filepath: &str,
+    lines: &[String],
+    languages: &HashMap<String, LanguageConfig>,
+) -> Option<String> {
+    let path = Path::new(filepath);
+    modeline(lines)
+        .or_else(|| shebang(lines, languages))
+        .or_else(|| {
+            let name = path.file_name()?.to_str()?;
+            lookup(name, languages, |config| &config.file_names, FILE_NAMES)
+        })
+        .or_else(|| {
+            let extension = path.extension()?.to_str()?;
+            lookup(
+                extension,
+                languages,
+                |config| &config.extensions,
+                EXTENSIONS,
+            )
+        })
+}
+
+// The configuration is consulted before the built-in table
+fn lookup(
+    key: &str,
+    languages: &HashMap<String, LanguageConfig>,
+    configured: impl Fn(&LanguageConfig) -> &Vec<String>,
+    builtin: &[(&str, &str)],
+) -> Option<String> {
+    languages
+        .iter()
+        .find(|(_, config)| {
+            configured(config)
+                .iter()
+                .any(|configured| configured == key)
+        })
+        .map(|(language, _)| language.clone())
+        .or_else(|| {
+            builtin
+                .iter()
+                .find(|(builtin, _)| *builtin == key)
+                .map(|(_, language)| language.to_string())
+        })
+}
+
+// "#!/usr/bin/python3" or "#!/usr/bin/env -S python3 -u"
+fn shebang(lines: &[String], languages: &HashMap<String, LanguageConfig>) -> Option<String> {
+    let mut words = lines.first()?.strip_prefix("#!")?.split_whitespace();
+    let mut program = Path::new(words.next()?).file_name()?.to_str()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    lookup(
+        program,
+        languages,
+        |config| &config.interpreters,
+        INTERPRETERS,
+    )
+}
+
+fn modeline(lines: &[String]) -> Option<String> {
+    let tail = lines.len().saturating_sub(MODELINE_LINES);
+    let name = lines
+        .iter()
+        .take(MODELINE_LINES)
+        .chain(lines.iter().skip(cmp::max(tail, MODELINE_LINES)))
+        .find_map(|line| vim_modeline(line).or_else(|| emacs_modeline(line)))?
+        .to_lowercase();
+    Some(
+        ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name.clone(), |(_, language)| language.to_string()),
+    )
+}
+
+// "vim: set ft=rust:" or "vi: filetype=python"
+fn vim_modeline(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(index, _)| *index == 0 || line[..*index].ends_with(char::is_whitespace))
+            .map(|(index, marker)| index + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+        })
+}
+
+// "-*- mode: rust -*-" or "-*- rust -*-"
+fn emacs_modeline(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+    let variables = variables.trim();
+    if !variables.contains(':') {
+        return Some(variables).filter(|mode| !mode.is_empty());
+    }
+    variables.split(';').find_map(|variable| {
+        let (name, value) = variable.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("mode")
+            .then(|| value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_lines(lines: &[&str]) -> Option<String> {
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        detect("script", &lines, &HashMap::new())
+    }
+
+    #[test]
+    fn reads_shebangs() {
+        assert_eq!(detect_lines(&["#!/bin/sh"]).as_deref(), Some("shellscript"));
+        assert_eq!(
+            detect_lines(&["#!/usr/bin/python3"]).as_deref(),
+            Some("python")
+        );
+        assert_eq!(
+            detect_lines(&["#!/usr/bin/env python3.12"]).as_deref(),
+            Some("python")
+        );
+        assert_eq!(
+            detect_lines(&["#!/usr/bin/env -S node --harmony"]).as_deref(),
+            Some("javascript")
+        );
+        assert_eq!(detect_lines(&["#!/usr/bin/env"]), None);
+    }
+
+    #[test]
+    fn prefers_configured_interpreters() {
+        let mut languages = HashMap::new();
+        languages.insert(
+            "typescript".to_string(),
+            LanguageConfig {
+                interpreters: vec!["node".to_string()],
+                ..Default::default()
+            },
+        );
+        let lines = vec!["#!/usr/bin/env node".to_string()];
+        assert_eq!(
+            detect("script", &lines, &languages).as_deref(),
+            Some("typescript")
+        );
+    }
+
+    #[test]
+    fn reads_vim_modelines() {
+        assert_eq!(
+            detect_lines(&["# vim: set ft=python:"]).as_deref(),
+            Some("python")
+        );
+        assert_eq!(
+            detect_lines(&["// vi: filetype=rs"]).as_deref(),
+            Some("rust")
+        );
+        assert_eq!(detect_lines(&["novim: ft=python"]), None);
+    }
+
+    #[test]
+    fn reads_emacs_modelines() {
+        assert_eq!(
+            detect_lines(&["/* -*- mode: C++; indent-tabs-mode: nil -*- */"]).as_deref(),
+            Some("cpp")
+        );
+        assert_eq!(detect_lines(&[";; -*- lua -*-"]).as_deref(), Some("lua"));
+        assert_eq!(detect_lines(&["-*- -*-"]), None);
+    }
+
+    #[test]
+    fn searches_modelines_at_both_ends_only() {
+        let mut lines = vec![""; 20];
+        lines[10] = "# vim: ft=python";
+        assert_eq!(detect_lines(&lines), None);
+        lines[19] = "# vim: ft=ruby";
+        assert_eq!(detect_lines(&lines).as_deref(), Some("ruby"));
+    }
+
+    #[test]
+    fn prefers_modelines_over_shebangs_and_extensions() {
+        let lines = vec!["#!/bin/sh".to_string(), "# vim: ft=zsh".to_string()];
+        assert_eq!(
+            detect("a.py", &lines, &HashMap::new()).as_deref(),
+            Some("shellscript")
+        );
+        assert_eq!(
+            detect("a.py", &[], &HashMap::new()).as_deref(),
+            Some("python")
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt;
+
+use crate::message::ResponseError;
+
+#[derive(Debug)]
+pub enum Error {
+    Response(String, ResponseError), // (method, error) answered by the server
+    Cancelled(String),               // Superseded or cancelled by the editor
+    TimedOut(String),
+    Closed(String), // The connection to the server is gone
+}
+
+impl Error {
+    // Errors the user does not need to hear about
+    pub fn is_silent(&self) -> bool {
+        match self {
+            Error::Response(_, error) => {
+                error.code == ResponseError::REQUEST_CANCELLED
+                    || error.code == ResponseError::CONTENT_MODIFIED
+            }
+            Error::Cancelled(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Response(method, error) => write!(f, "{} FAILED: {}", method, error.message),
+            Error::Cancelled(method) => write!(f, "{} CANCELLED", method),
+            Error::TimedOut(method) => write!(f, "{} TIMED OUT", method),
+            Error::Closed(method) => write!(f, "NO LANGUAGE SERVER RUNNING FOR {}", method),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
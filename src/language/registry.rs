@@ -0,0 +1,93 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::sync::mpsc;
+
+use super::{find_root, initialize, Client};
+use crate::config::Config;
+use crate::message::{Event, Id};
+
+// Index of a server in the registry, carried by its events
+pub type ServerId = usize;
+
+// The servers of the session, started when a file of their language is opened
+pub struct Registry {
+    config: Config,
+    event_sender: mpsc::UnboundedSender<Event>,
+    clients: Vec<Client>,
+    started: HashSet<String>, // Languages whose servers were started, even if they failed
+}
+
+impl Registry {
+    pub fn new(config: Config, event_sender: mpsc::UnboundedSender<Event>) -> Self {
+        Registry {
+            config,
+            event_sender,
+            clients: Vec::new(),
+            started: HashSet::new(),
+        }
+    }
+
+    pub fn started(&self, language: &str) -> bool {
+        self.started.contains(language)
+    }
+
+    // Only once per language, with the workspace folder of the file,
+    // along with the initialize request of each server
+    pub async fn start(&mut self, language: &str, filepath: &Path) -> Vec<Result<(ServerId, Id)>> {
+        if !self.started.insert(language.to_string()) {
+            return Vec::new();
+        }
+        let Some(servers) = self.config.language_servers.get(language) else {
+            return Vec::new();
+        };
+        let root = find_root(filepath, language, &self.config.languages);
+
+        let mut started = Vec::new();
+        for server in servers.clone() {
+            let id = self.clients.len();
+            let started_server = initialize(
+                id,
+                language.to_string(),
+                server,
+                root.clone(),
+                self.event_sender.clone(),
+            )
+            .await;
+            started.push(started_server.map(|(client, initialize)| {
+                self.clients.push(client);
+                (id, initialize)
+            }));
+        }
+        started
+    }
+
+    pub fn get(&mut self, id: ServerId) -> Option<&mut Client> {
+        self.clients.get_mut(id)
+    }
+
+    pub fn all(&mut self) -> impl Iterator<Item = &mut Client> {
+        self.clients.iter_mut()
+    }
+
+    pub fn clients<'a>(&'a mut self, language: &'a str) -> impl Iterator<Item = &'a mut Client> {
+        self.clients
+            .iter_mut()
+            .filter(move |client| client.language == language)
+    }
+
+    // The first server of the language supporting the capability, in the configured order
+    pub fn find(&mut self, language: Option<&str>, capability: &str) -> Option<&mut Client> {
+        let language = language?;
+        self.clients
+            .iter_mut()
+            .find(|client| client.language == language && client.supports(capability))
+    }
+
+    // On exit, so failures do not matter
+    pub async fn shutdown(&mut self) {
+        for client in &mut self.clients {
+            let _ = client.shutdown().await;
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+
+// Messages kept for viewing in the editor, the oldest dropped first
+const RECENT_LIMIT: usize = 1000;
+
+pub type Tracer = Arc<Mutex<Trace>>;
+
+// The messages exchanged with a server, recorded while the level is not "off"
+pub struct Trace {
+    name: String, // Of the server
+    level: String,
+    file: Option<fs::File>,
+    recent: VecDeque<String>,
+}
+
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Trace {
+    pub fn new(name: String) -> Tracer {
+        Arc::new(Mutex::new(Trace {
+            name,
+            level: "off".to_string(),
+            file: None,
+            recent: VecDeque::new(),
+        }))
+    }
+
+    // "off", "messages" or "verbose", as sent with $/setTrace
+    pub fn level(&self) -> &str {
+        &self.level
+    }
+
+    // Appends to $XDG_STATE_HOME/sura/<server>.trace, opened when tracing first starts
+    pub fn set_level(&mut self, level: &str) -> Result<()> {
+        if !["off", "messages", "verbose"].contains(&level) {
+            bail!("UNKNOWN TRACE LEVEL {}", level);
+        }
+        if level != "off" && self.file.is_none() {
+            let path = config::state_dir()?.join(format!("{}.trace", self.name));
+            self.file = Some(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?,
+            );
+        }
+        self.level = level.to_string();
+        Ok(())
+    }
+
+    pub fn record(&mut self, direction: Direction, content: &[u8]) {
+        if self.level == "off" {
+            return;
+        }
+        let arrow = match direction {
+            Direction::Sent => "-->",
+            Direction::Received => "<--",
+        };
+        let line = format!(
+            "{} {} {}",
+            timestamp(),
+            arrow,
+            String::from_utf8_lossy(content)
+        );
+        // Losing a line of the trace is not worth stopping the editor
+        if let Some(file) = &mut self.file {
+            let _ = writeln!(file, "{}", line);
+        }
+        self.recent.push_back(line);
+        if self.recent.len() > RECENT_LIMIT {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn recent(&self) -> Vec<String> {
+        self.recent.iter().cloned().collect()
+    }
+}
+
+// A panic while recording does not stop the tracing
+pub fn lock_trace(tracer: &Tracer) -> MutexGuard<'_, Trace> {
+    tracer.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// UTC time of day with milliseconds, enough to line the messages up
+fn timestamp() -> String {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = elapsed.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        elapsed.subsec_millis()
+    )
+}
@@ -0,0 +1,357 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationLink {
+    pub origin_selection_range: Option<Range>,
+    pub target_uri: String,
+    pub target_range: Range,
+    pub target_selection_range: Range,
+}
+
+// Result of textDocument/definition and its relatives
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum GotoResult {
+    Scalar(Location),
+    Locations(Vec<Location>),
+    Links(Vec<LocationLink>),
+    Null,
+}
+
+impl From<GotoResult> for Vec<Location> {
+    fn from(result: GotoResult) -> Self {
+        match result {
+            GotoResult::Scalar(location) => vec![location],
+            GotoResult::Locations(locations) => locations,
+            GotoResult::Links(links) => links
+                .into_iter()
+                .map(|link| Location {
+                    uri: link.target_uri,
+                    range: link.target_selection_range,
+                })
+                .collect(),
+            GotoResult::Null => Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TextEdit {
+    pub range: Range,
+    pub new_text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceEdit {
+    pub changes: Option<HashMap<String, Vec<TextEdit>>>,
+    pub document_changes: Option<Vec<DocumentChange>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum DocumentChange {
+    Operation(ResourceOperation),
+    Edit(TextDocumentEdit),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentEdit {
+    pub text_document: VersionedTextDocumentIdentifier,
+    pub edits: Vec<TextEdit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VersionedTextDocumentIdentifier {
+    pub uri: String,
+    pub version: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ResourceOperation {
+    Create {
+        uri: String,
+        #[serde(default)]
+        options: FileOptions,
+    },
+    #[serde(rename_all = "camelCase")]
+    Rename {
+        old_uri: String,
+        new_uri: String,
+        #[serde(default)]
+        options: FileOptions,
+    },
+    Delete {
+        uri: String,
+        #[serde(default)]
+        options: FileOptions,
+    },
+}
+
+// Options of create, rename and delete file operations
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOptions {
+    #[serde(default)]
+    pub overwrite: bool,
+    #[serde(default)]
+    pub ignore_if_exists: bool,
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default)]
+    pub ignore_if_not_exists: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum PrepareRenameResult {
+    Range(Range),
+    Placeholder {
+        placeholder: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    DefaultBehavior {
+        default_behavior: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Option<u8>,
+    pub message: String,
+    // code, source, data and so on, sent back as they are
+    #[serde(flatten)]
+    pub rest: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PublishDiagnosticsParams {
+    pub uri: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Command {
+    pub title: String,
+    pub command: String,
+    pub arguments: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CodeAction {
+    pub title: String,
+    pub disabled: Option<serde_json::Value>,
+    pub edit: Option<WorkspaceEdit>,
+    pub command: Option<Command>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum CodeActionOrCommand {
+    Command(Command),
+    CodeAction(CodeAction),
+}
+
+impl CodeActionOrCommand {
+    pub fn title(&self) -> &str {
+        match self {
+            CodeActionOrCommand::Command(command) => &command.title,
+            CodeActionOrCommand::CodeAction(action) => &action.title,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureHelp {
+    pub signatures: Vec<SignatureInformation>,
+    pub active_signature: Option<u32>,
+    pub active_parameter: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureInformation {
+    pub label: String,
+    #[serde(default)]
+    pub parameters: Vec<ParameterInformation>,
+    pub active_parameter: Option<u32>, // Wins over the one of SignatureHelp
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParameterInformation {
+    pub label: ParameterLabel,
+}
+
+// A substring of the signature label, or its UTF-16 offsets
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ParameterLabel {
+    Text(String),
+    Offsets([u32; 2]),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub detail: Option<String>,
+    pub kind: u32,
+    pub selection_range: Range,
+    #[serde(default)]
+    pub children: Vec<DocumentSymbol>,
+}
+
+// Also WorkspaceSymbol, whose location may lack the range
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInformation {
+    pub name: String,
+    pub kind: u32,
+    pub location: SymbolLocation,
+    pub container_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SymbolLocation {
+    pub uri: String,
+    pub range: Option<Range>,
+}
+
+impl From<SymbolLocation> for Location {
+    fn from(location: SymbolLocation) -> Self {
+        let start = Position {
+            line: 0,
+            character: 0,
+        };
+        Location {
+            uri: location.uri,
+            range: location.range.unwrap_or(Range { start, end: start }),
+        }
+    }
+}
+
+// Result of textDocument/documentSymbol
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum DocumentSymbolResult {
+    Nested(Vec<DocumentSymbol>),
+    Flat(Vec<SymbolInformation>),
+    Null,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: Position,
+    pub label: InlayHintLabel,
+    #[serde(default)]
+    pub padding_left: bool,
+    #[serde(default)]
+    pub padding_right: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum InlayHintLabel {
+    Text(String),
+    Parts(Vec<InlayHintLabelPart>),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InlayHintLabelPart {
+    pub value: String,
+}
+
+impl InlayHint {
+    // The label with its padding
+    pub fn text(&self) -> String {
+        let label = match &self.label {
+            InlayHintLabel::Text(text) => text.clone(),
+            InlayHintLabel::Parts(parts) => parts.iter().map(|part| part.value.as_str()).collect(),
+        };
+        let left = if self.padding_left { " " } else { "" };
+        let right = if self.padding_right { " " } else { "" };
+        format!("{}{}{}", left, label, right)
+    }
+}
+
+// CallHierarchyItem and TypeHierarchyItem
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HierarchyItem {
+    pub name: String,
+    pub kind: u32,
+    pub detail: Option<String>,
+    pub uri: String,
+    pub selection_range: Range,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DocumentHighlight {
+    pub range: Range,
+    pub kind: Option<u8>, // 1: text, 2: read, 3: write
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokens {
+    pub result_id: Option<String>,
+    pub data: Vec<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensDelta {
+    pub result_id: Option<String>,
+    pub edits: Vec<SemanticTokensEdit>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensEdit {
+    pub start: usize,
+    pub delete_count: usize,
+    #[serde(default)]
+    pub data: Vec<u32>,
+}
+
+// Result of textDocument/semanticTokens/full/delta, which may send all the tokens again
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SemanticTokensResult {
+    Delta(SemanticTokensDelta),
+    Full(SemanticTokens),
+    Null,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FoldingRange {
+    pub start_line: u32,
+    pub end_line: u32,
+}
@@ -0,0 +1,105 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+// Characters left as they are in the path of a URI
+fn unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte)
+}
+
+pub fn path_to_uri(path: &str) -> io::Result<String> {
+    Ok(format!(
+        "file://{}",
+        encode(absolute(Path::new(path))?.as_os_str().as_bytes())
+    ))
+}
+
+// Only local files, with the host empty or "localhost"
+pub fn uri_to_path(uri: &str) -> Option<String> {
+    let path = uri.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    if !path.starts_with('/') {
+        return None;
+    }
+    String::from_utf8(decode(path)?).ok()
+}
+
+// Symlinks are resolved so that a file has a single URI, files not created yet keep their name
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    if let Ok(path) = fs::canonicalize(path) {
+        return Ok(path);
+    }
+    let path = env::current_dir()?.join(path);
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => Ok(fs::canonicalize(parent)
+            .unwrap_or_else(|_| parent.to_path_buf())
+            .join(name)),
+        _ => Ok(path),
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for &byte in bytes {
+        if unreserved(byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded += &format!("%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+fn decode(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_reserved_and_multibyte_characters() {
+        let path = "/tmp/a b/c#d%e/ü.rs";
+        let encoded = encode(path.as_bytes());
+        assert_eq!(encoded, "/tmp/a%20b/c%23d%25e/%C3%BC.rs");
+        assert_eq!(
+            uri_to_path(&format!("file://{}", encoded)).as_deref(),
+            Some(path)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        assert_eq!(uri_to_path("file:///tmp/%zz"), None);
+        assert_eq!(uri_to_path("file:///tmp/%2"), None);
+        assert_eq!(uri_to_path("file:///tmp/%FF"), None);
+    }
+
+    #[test]
+    fn accepts_localhost() {
+        assert_eq!(
+            uri_to_path("file://localhost/tmp/a.rs").as_deref(),
+            Some("/tmp/a.rs")
+        );
+    }
+
+    #[test]
+    fn rejects_other_hosts_and_schemes() {
+        assert_eq!(uri_to_path("file://example.com/tmp/a.rs"), None);
+        assert_eq!(uri_to_path("https://example.com/a.rs"), None);
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::LanguageConfig;
+
+// Files marking the root of a project, when the configuration has none
+const ROOT_MARKERS: &[(&str, &[&str])] = &[
+    ("rust", &["Cargo.toml"]),
+    ("go", &["go.mod"]),
+    ("python", &["pyproject.toml", "setup.py", "setup.cfg"]),
+    ("javascript", &["package.json", "jsconfig.json"]),
+    ("javascriptreact", &["package.json", "jsconfig.json"]),
+    ("typescript", &["package.json", "tsconfig.json"]),
+    ("typescriptreact", &["package.json", "tsconfig.json"]),
+    (
+        "c",
+        &["compile_commands.json", "CMakeLists.txt", "Makefile"],
+    ),
+    (
+        "cpp",
+        &["compile_commands.json", "CMakeLists.txt", "Makefile"],
+    ),
+    ("java", &["pom.xml", "build.gradle"]),
+    ("haskell", &["stack.yaml", "cabal.project"]),
+    ("elixir", &["mix.exs"]),
+    ("zig", &["build.zig"]),
+];
+
+// Marks a project of any language
+const REPOSITORY_MARKERS: &[&str] = &[".git", ".hg", ".jj"];
+
+// The nearest directory with a marker of the language, then the nearest repository,
+// then the directory of the file
+pub fn find_root(
+    filepath: &Path,
+    language: &str,
+    languages: &HashMap<String, LanguageConfig>,
+) -> PathBuf {
+    let filepath = fs::canonicalize(filepath).unwrap_or(filepath.to_path_buf());
+    let directory = filepath.parent().unwrap_or(Path::new("/"));
+    let configured = languages
+        .get(language)
+        .map(|config| &config.root_markers)
+        .filter(|markers| !markers.is_empty());
+    let found = match configured {
+        Some(markers) => find_marker(directory, markers),
+        None => ROOT_MARKERS
+            .iter()
+            .find(|(markers_language, _)| *markers_language == language)
+            .and_then(|(_, markers)| find_marker(directory, markers)),
+    };
+    found
+        .or_else(|| find_marker(directory, REPOSITORY_MARKERS))
+        .unwrap_or(directory.to_path_buf())
+}
+
+fn find_marker(directory: &Path, markers: &[impl AsRef<str>]) -> Option<PathBuf> {
+    directory
+        .ancestors()
+        .find(|ancestor| {
+            markers
+                .iter()
+                .any(|marker| ancestor.join(marker.as_ref()).exists())
+        })
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // A directory of its own with the given files, removed when dropped
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str, files: &[&str]) -> Tree {
+            let root = env::temp_dir().join(format!("sura-{}-{}", process::id(), name));
+            for file in files {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+            Tree(fs::canonicalize(root).unwrap())
+        }
+
+        fn root(
+            &self,
+            file: &str,
+            language: &str,
+            languages: &HashMap<String, LanguageConfig>,
+        ) -> PathBuf {
+            find_root(&self.0.join(file), language, languages)
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn finds_the_nearest_marker_of_the_language() {
+        let tree = Tree::new(
+            "nearest",
+            &[
+                "Cargo.toml",
+                "crates/a/Cargo.toml",
+                "crates/a/src/lib.rs",
+                "src/main.rs",
+            ],
+        );
+        let languages = HashMap::new();
+        assert_eq!(tree.root("src/main.rs", "rust", &languages), tree.0);
+        assert_eq!(
+            tree.root("crates/a/src/lib.rs", "rust", &languages),
+            tree.0.join("crates/a")
+        );
+        // Files not created yet are looked up from their directory too
+        assert_eq!(tree.root("src/new.rs", "rust", &languages), tree.0);
+    }
+
+    #[test]
+    fn prefers_the_configured_markers() {
+        let tree = Tree::new("configured", &["WORKSPACE", "sub/Cargo.toml", "sub/x.rs"]);
+        let mut languages = HashMap::new();
+        languages.insert(
+            "rust".to_string(),
+            LanguageConfig {
+                root_markers: vec!["WORKSPACE".to_string()],
+                ..Default::default()
+            },
+        );
+        assert_eq!(tree.root("sub/x.rs", "rust", &languages), tree.0);
+    }
+
+    #[test]
+    fn falls_back_to_the_repository_then_the_directory() {
+        let tree = Tree::new("fallback", &["repo/.git/HEAD", "repo/a/b.py", "c/d.zig"]);
+        let languages = HashMap::new();
+        assert_eq!(
+            tree.root("repo/a/b.py", "python", &languages),
+            tree.0.join("repo")
+        );
+        assert_eq!(tree.root("c/d.zig", "zig", &languages), tree.0.join("c"));
+    }
+}
@@ -5,7 +5,6 @@ mod message;
 mod terminal;
 
 use std::env;
-use std::fs;
 use tokio::sync::mpsc;
 
 use message::*;
@@ -18,23 +17,13 @@ async fn main() {
     if args.len() != 2 {
         panic!("ERROR: Select the input file");
     }
-    let content = fs::read_to_string(&args[1]).unwrap();
-
-    let mut editor = editor::Editor::new(args[1].to_string());
+    let mut editor = editor::Editor::new(args[1].to_string(), config.clone());
 
     let (event_sender, mut event_queue) = mpsc::unbounded_channel();
     tokio::spawn(terminal::listen(event_sender.clone()));
 
-    let mut client = language::initialize(
-        config.language_servers.get("rust").unwrap().clone(),
-        event_sender.clone(),
-    )
-    .await
-    .unwrap();
-
-    let uri = "file:///".to_string() + &args[1];
-
-    client.did_open("rust", &uri, &content).await.unwrap();
+    let mut servers = language::Registry::new(config.clone(), event_sender.clone());
+    editor.start_servers(&mut servers).await;
 
     loop {
         let event = event_queue.recv().await.unwrap();
@@ -43,20 +32,35 @@ async fn main() {
                 if let KeyPress::Control('Q') = keypress {
                     break;
                 }
-                editor.keypress_handler(keypress).await;
+                editor.keypress_handler(keypress, &mut servers).await;
+            }
+            Event::LanguageResponse(server, id, response) => {
+                editor
+                    .response_handler(server, id, response, &mut servers)
+                    .await;
+            }
+            Event::LanguageError(err) => {
+                editor.show_error(err);
             }
-            Event::LanguageNotification(response) => {
-                eprintln!(
-                    "NOTIFICATION\n{}",
-                    serde_json::to_string(&response).unwrap()
-                );
+            Event::LanguageRequest(server, request) => {
+                editor.request_handler(server, request, &mut servers).await;
+            }
+            Event::LanguageNotification(server, notification) => {
+                editor.notification_handler(server, notification, &mut servers);
+            }
+            Event::LanguageServerExit(server, pid, status) => {
+                editor.server_exit_handler(server, pid, status, &mut servers);
+            }
+            Event::LanguageServerRestart(server, pid) => {
+                editor
+                    .server_restart_handler(server, pid, &mut servers)
+                    .await;
             }
         }
     }
 
-    client.did_close(&uri).await.unwrap();
-
-    client.shutdown().await.unwrap();
+    editor.close_documents(&mut servers).await;
+    servers.shutdown().await;
     terminal::close();
     std::process::exit(0);
 }
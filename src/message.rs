@@ -4,8 +4,19 @@ mod lsp;
 pub use keypress::*;
 pub use lsp::*;
 
+use crate::language;
+
 #[derive(Debug)]
 pub enum Event {
     KeyPress(KeyPress),
-    LanguageNotification(Notification),
+    LanguageRequest(language::ServerId, Request),
+    LanguageResponse(
+        language::ServerId,
+        Id,
+        Result<serde_json::Value, language::Error>,
+    ), // To Client::spawn_request
+    LanguageError(anyhow::Error),
+    LanguageNotification(language::ServerId, Notification),
+    LanguageServerExit(language::ServerId, u32, String), // Process ID and exit status
+    LanguageServerRestart(language::ServerId, u32),      // Process ID of the server to replace
 }
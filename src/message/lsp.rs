@@ -1,30 +1,48 @@
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum ClientMessage {
     Request(Request),
+    Response(Response),
     Notification(Notification),
 }
 
+// JSON-RPC allows both numbers and strings as request IDs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum Id {
+    Number(i64),
+    String(String),
+}
+
+impl Id {
+    // Some servers answer a numeric ID as a string
+    pub fn normalize(&self) -> Id {
+        match self {
+            Id::String(id) => match id.parse() {
+                Ok(id) => Id::Number(id),
+                Err(_) => self.clone(),
+            },
+            Id::Number(_) => self.clone(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Request {
     pub jsonrpc: String,
-    pub id: i32,
+    pub id: Id,
     pub method: String,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub params: serde_json::Value,
 }
 
 impl Request {
-    pub fn new(method: &str, params: serde_json::Value) -> Self {
+    pub fn new(id: Id, method: &str, params: serde_json::Value) -> Self {
         Request {
             jsonrpc: String::from("2.0"),
-            id: (SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-                / i32::MAX as u128) as i32,
+            id,
             method: String::from(method),
             params,
         }
@@ -34,6 +52,7 @@ impl Request {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum ServerMessage {
+    Request(Request), // Requests have both id and method
     Response(Response),
     Notification(Notification),
 }
@@ -41,22 +60,66 @@ pub enum ServerMessage {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Response {
     pub jsonrpc: String,
-    pub id: i32,
-    pub result: serde_json::Value,
+    pub id: Id,
+    // Exactly one of result and error exists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub error: Option<ResponseError>,
 }
 
+impl Response {
+    pub fn new(id: Id, result: serde_json::Value) -> Self {
+        Response {
+            jsonrpc: String::from("2.0"),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn into_result(self) -> Result<serde_json::Value, ResponseError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.result.unwrap_or_default()),
+        }
+    }
+
+    pub fn error(id: Id, code: i32, message: &str) -> Self {
+        Response {
+            jsonrpc: String::from("2.0"),
+            id,
+            result: None,
+            error: Some(ResponseError {
+                code,
+                message: String::from(message),
+                data: None,
+            }),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResponseError {
     pub code: i32,
     pub message: String,
-    pub data: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl ResponseError {
+    pub const REQUEST_CANCELLED: i32 = -32800;
+    pub const CONTENT_MODIFIED: i32 = -32801;
+    pub const INVALID_PARAMS: i32 = -32602;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INTERNAL_ERROR: i32 = -32603;
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Notification {
     pub jsonrpc: String,
     pub method: String,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub params: serde_json::Value,
 }
 
@@ -69,3 +132,69 @@ impl Notification {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> ServerMessage {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn reads_a_response_with_a_result() {
+        let ServerMessage::Response(response) =
+            parse(r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{}}}"#)
+        else {
+            panic!("NOT A RESPONSE");
+        };
+        assert_eq!(response.id, Id::Number(1));
+        assert_eq!(
+            response.into_result().unwrap(),
+            serde_json::json!({ "capabilities": {} })
+        );
+    }
+
+    #[test]
+    fn reads_a_null_result_as_null() {
+        let ServerMessage::Response(response) = parse(r#"{"jsonrpc":"2.0","id":2,"result":null}"#)
+        else {
+            panic!("NOT A RESPONSE");
+        };
+        assert_eq!(response.into_result().unwrap(), serde_json::Value::Null);
+    }
+
+    #[test]
+    fn reads_a_response_with_only_an_error() {
+        let ServerMessage::Response(response) =
+            parse(r#"{"jsonrpc":"2.0","id":"3","error":{"code":-32800,"message":"cancelled"}}"#)
+        else {
+            panic!("NOT A RESPONSE");
+        };
+        assert_eq!(response.id.normalize(), Id::Number(3));
+        let error = response.into_result().unwrap_err();
+        assert_eq!(error.code, ResponseError::REQUEST_CANCELLED);
+    }
+
+    #[test]
+    fn reads_a_request_with_a_string_id() {
+        let ServerMessage::Request(request) = parse(
+            r#"{"jsonrpc":"2.0","id":"abc","method":"workspace/configuration","params":{"items":[]}}"#,
+        ) else {
+            panic!("NOT A REQUEST");
+        };
+        assert_eq!(request.id, Id::String("abc".to_string()));
+        assert_eq!(request.method, "workspace/configuration");
+    }
+
+    #[test]
+    fn reads_a_notification_without_params() {
+        let ServerMessage::Notification(notification) =
+            parse(r#"{"jsonrpc":"2.0","method":"exit"}"#)
+        else {
+            panic!("NOT A NOTIFICATION");
+        };
+        assert_eq!(notification.method, "exit");
+        assert!(notification.params.is_null());
+    }
+}
@@ -17,6 +17,32 @@ pub fn clear_line() {
     send_escape_sequence_csi("2K");
 }
 
+pub fn reverse_video() {
+    send_escape_sequence_csi("7m");
+}
+
+// SGR code of the color, such as 31 for red
+pub fn foreground(color: u8) {
+    send_escape_sequence_csi(&format!("{}m", color));
+}
+
+// The background code is 10 above the foreground one
+pub fn background(color: u8) {
+    send_escape_sequence_csi(&format!("{}m", color + 10));
+}
+
+pub fn faint() {
+    send_escape_sequence_csi("2m");
+}
+
+pub fn underline() {
+    send_escape_sequence_csi("4m");
+}
+
+pub fn reset_style() {
+    send_escape_sequence_csi("0m");
+}
+
 pub fn move_cursor(row: usize, column: usize) {
     send_escape_sequence_csi(format!("{};{}H", row, column).as_str());
 }
@@ -34,7 +60,7 @@ pub fn close() {
 pub async fn listen(tx: UnboundedSender<Event>) {
     let mut buf = [0];
     loop {
-        tokio::io::stdin().read(&mut buf).await.unwrap();
+        tokio::io::stdin().read_exact(&mut buf).await.unwrap();
         if buf[0] > 0x7F {
             todo!(); // UTF-8
         }
@@ -56,10 +82,10 @@ pub async fn listen(tx: UnboundedSender<Event>) {
 
 async fn read_escape_sequence() -> KeyPress {
     let mut buf = [0];
-    tokio::io::stdin().read(&mut buf).await.unwrap();
+    tokio::io::stdin().read_exact(&mut buf).await.unwrap();
     match buf[0] {
         0x5B => {
-            tokio::io::stdin().read(&mut buf).await.unwrap();
+            tokio::io::stdin().read_exact(&mut buf).await.unwrap();
             match buf[0] {
                 0x41 => KeyPress::CursorUp,
                 0x42 => KeyPress::CursorDown,